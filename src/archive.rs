@@ -0,0 +1,239 @@
+/*
+Encrypting whole folders. The folder is packed into a tar archive, which keeps the paths
+of its files and folders, their permissions and modification times, and symbolic links
+as links, and the archive is streamed through the encryptor as it is written, so it never
+exists unencrypted on disk. The header marks such a file as an archive (see container.rs),
+the folder's name is sealed with it like a file's (see metadata.rs), and the payload is
+authenticated chunk by chunk like any other.
+
+The paths in an archive come from the file being decrypted, so they are not trusted:
+decrypt_folder refuses absolute paths and paths that climb out with "..", and the tar
+crate refuses to write through links that point outside the output folder. Devices and
+FIFOs are skipped, files get no more than their rwx permission bits, and the folder is
+extracted into a temporary folder next to the output that only takes the output's name
+once every chunk has been verified, so a modified archive never leaves a partial folder.
+ */
+use crate::compression::{self, Encoder};
+use crate::container::Header;
+use crate::error::Error;
+use crate::files::{self, DecryptOptions, EncryptOptions, ProgressReader, Unlocked};
+use crate::metadata::Metadata;
+use crate::stream::{StreamReader, StreamWriter};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tar::{Archive, Builder, Entry, EntryType};
+
+// Size of a tar header block, and the unit file contents are padded to.
+const BLOCK_SIZE: u64 = 512;
+
+//This function packs the folder at `input_dir` into an archive and encrypts it into
+// `output_path`, the same way encrypt_file encrypts a file. The progress callback gets the
+// number of archive bytes written so far, of about folder_size(input_dir). Folders are never
+// removed afterwards, so `remove_original` must not be set.
+pub fn encrypt_folder(
+    input_dir: &Path,
+    output_path: &Path,
+    password: &str,
+    options: &EncryptOptions,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<(), Error> {
+    options.check_password(password)?;
+    if options.remove_original {
+        return Err(Error::InvalidOptions(String::from("folders are not removed after encrypting")));
+    }
+    let metadata = fs::metadata(input_dir).map_err(|e| Error::with_path(e, input_dir))?;
+    if !metadata.is_dir() {
+        return Err(Error::InvalidOptions(format!("{} is not a folder", input_dir.display())));
+    }
+    // The output would otherwise end up in the archive, half written.
+    if let (Ok(input), Ok(output)) = (fs::canonicalize(input_dir), fs::canonicalize(files::parent_dir(output_path))) {
+        if output.starts_with(input) {
+            return Err(Error::InvalidOptions(format!(
+                "the output {} is inside the folder being encrypted",
+                output_path.display()
+            )));
+        }
+    }
+    files::check_output(input_dir, output_path, options.overwrite)?;
+
+    let folder_name = input_dir.canonicalize().ok().and_then(|p| p.file_name().and_then(|n| n.to_str()).map(String::from));
+    let (mut header, key) = options.new_header(password, None, Some(Metadata { name: folder_name, ..Metadata::default() }))?;
+    header.archive = true;
+
+    files::write_output(output_path, options.overwrite, |output| {
+        header.write_to(output)?;
+        let header_bytes = header.authenticated_bytes()?;
+        let writer = StreamWriter::new(output, header.cipher, key.as_bytes(), &header.nonce, header.chunk_size, header_bytes);
+        let writer = Encoder::new(writer, header.compression)?;
+
+        let mut builder = Builder::new(ProgressWriter { inner: writer, processed: 0, progress: &mut *progress });
+        builder.follow_symlinks(false);
+        // The folder itself goes first, so it is restored with its permissions and time.
+        builder.append_dir(".", input_dir)?;
+        builder.append_dir_all("", input_dir)?;
+        builder.into_inner()?.inner.finish()?.finish()?;
+        Ok(())
+    })
+}
+
+//This function decrypts an encrypted folder into `output_dir`, which must not exist unless
+// `overwrite` is set; an existing file or folder (with everything in it) is then replaced,
+// but only once the whole archive has been extracted and verified. Paths in the archive
+// that lead outside the output folder fail with Error::UnsafeArchivePath.
+pub fn decrypt_folder(
+    input_path: &Path,
+    output_dir: &Path,
+    password: &str,
+    options: &DecryptOptions,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<(), Error> {
+    files::check_output(input_path, output_dir, options.overwrite)?;
+    decrypt_folder_named(input_path, password, options, &mut |_| Ok(output_dir.to_path_buf()), progress).map(|_| ())
+}
+
+//Decrypts a folder like decrypt_folder, into a path chosen once the key has been found,
+// the way files::decrypt_file_named does for files. Returns the path written.
+pub fn decrypt_folder_named(
+    input_path: &Path,
+    password: &str,
+    options: &DecryptOptions,
+    output_for: &mut dyn FnMut(Option<&str>) -> Result<PathBuf, Error>,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<PathBuf, Error> {
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let mut input = ProgressReader::new(BufReader::new(file), progress);
+    let (header, header_bytes) = Header::read_from(&mut input)?;
+    if !header.archive {
+        return Err(Error::InvalidOptions(format!("{} is an encrypted file, not a folder", input_path.display())));
+    }
+    let unlocked = files::unlock(header, header_bytes, input, password, options)?;
+    let output_dir = output_for(unlocked.original_name())?;
+    files::check_output(input_path, &output_dir, options.overwrite)?;
+    let Unlocked { header, header_bytes, key, input, .. } = unlocked;
+    let mut reader = StreamReader::new(input, header.cipher, key.as_bytes(), &header.nonce, header.chunk_size, header_bytes);
+
+    let temp = files::temp_file_beside(&output_dir, |builder, dir| builder.tempdir_in(dir))?;
+    extract(compression::decoder(&mut reader, header.compression)?, temp.path())?;
+    files::read_to_end(&mut reader)?;
+
+    if let Ok(metadata) = fs::symlink_metadata(&output_dir) {
+        if !options.overwrite {
+            return Err(Error::AlreadyExists(output_dir.clone()));
+        }
+        let removed = if metadata.is_dir() { fs::remove_dir_all(&output_dir) } else { fs::remove_file(&output_dir) };
+        removed.map_err(|e| Error::with_path(e, &output_dir))?;
+    }
+    fs::rename(temp.path(), &output_dir).map_err(|e| Error::with_path(e, &output_dir))?;
+    files::sync_dir(files::parent_dir(&output_dir));
+    Ok(output_dir)
+}
+
+//Returns about how many bytes the archive of the folder at `path` takes, which is the
+// total encrypt_folder's progress counts towards. Entries that cannot be read are left out.
+pub fn folder_size(path: &Path) -> u64 {
+    // The folder's own entry, and the two empty blocks that end an archive.
+    3 * BLOCK_SIZE + entries_size(path)
+}
+
+fn entries_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    let mut size = 0;
+    for entry in entries.flatten() {
+        size += match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => BLOCK_SIZE + entries_size(&entry.path()),
+            Ok(metadata) if metadata.is_file() => BLOCK_SIZE + metadata.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE,
+            Ok(_) => BLOCK_SIZE,
+            Err(_) => 0,
+        };
+    }
+    size
+}
+
+// Extracts the archive read from `reader` into `dir`. Folders are created as files need
+// them and only get their permissions and times at the end, deepest first, so a read-only
+// folder can still be filled. Everything after the end of the archive is read too, so
+// the payload is checked to its end.
+fn extract<R: Read>(reader: R, dir: &Path) -> Result<(), Error> {
+    let mut archive = Archive::new(reader);
+    archive.set_preserve_permissions(false);
+    archive.set_preserve_mtime(true);
+    archive.set_unpack_xattrs(false);
+
+    let mut folders = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        check_path(&path)?;
+        match entry.header().entry_type() {
+            EntryType::Directory => folders.push((path, entry)),
+            EntryType::Regular | EntryType::Continuous | EntryType::Symlink => unpack(&mut entry, &path, dir)?,
+            EntryType::Link => {
+                if let Some(target) = entry.link_name()? {
+                    check_path(&target)?;
+                }
+                unpack(&mut entry, &path, dir)?;
+            }
+            _ => {}
+        }
+    }
+
+    folders.sort_by(|a, b| b.0.cmp(&a.0));
+    for (path, mut entry) in folders {
+        if path.components().all(|c| c == Component::CurDir) {
+            // unpack_in leaves the output folder itself alone.
+            entry.unpack(dir)?;
+        } else {
+            unpack(&mut entry, &path, dir)?;
+        }
+        // The tar crate only sets the times of files. Failing to is not worth an error.
+        if let Ok(mtime) = entry.header().mtime() {
+            let _ = File::open(dir.join(&path)).and_then(|f| f.set_modified(UNIX_EPOCH + Duration::from_secs(mtime)));
+        }
+    }
+
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(())
+}
+
+fn unpack<R: Read>(entry: &mut Entry<'_, R>, path: &Path, dir: &Path) -> Result<(), Error> {
+    if !entry.unpack_in(dir)? {
+        return Err(Error::UnsafeArchivePath(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+// Only paths made of plain names (and ".") stay inside the output folder.
+fn check_path(path: &Path) -> Result<(), Error> {
+    if path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        Ok(())
+    } else {
+        Err(Error::UnsafeArchivePath(path.to_path_buf()))
+    }
+}
+
+// Counts the bytes written through it and hands the running total to the progress
+// callback. When the callback returns false, the next write fails with Error::Cancelled.
+struct ProgressWriter<'a, W: Write> {
+    inner: W,
+    processed: u64,
+    progress: &'a mut dyn FnMut(u64) -> bool,
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !(self.progress)(self.processed) {
+            return Err(Error::Cancelled.into_io());
+        }
+        let n = self.inner.write(buf)?;
+        self.processed += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
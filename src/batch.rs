@@ -0,0 +1,171 @@
+/*
+The list of files the GUI works through. Files are added from the file dialog, one or
+many at a time, or a whole folder at once (including its subfolders). They are then
+processed one after the other with the same password, and each keeps its own status so
+the window can show what happened to it and sum up the batch at the end.
+ */
+use aes256_encryption_gui_app::container::Header;
+use aes256_encryption_gui_app::{files, Error};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+//ItemStatus is where a file is in the batch. Finished holds the path of the file written,
+// Verified the header of a file that was checked and found intact.
+pub enum ItemStatus {
+    Pending,
+    Running,
+    Finished(PathBuf),
+    Verified(Header),
+    Cancelled,
+    Skipped,
+    Failed(Error),
+}
+
+#[derive(Debug, Clone)]
+//Item is a queued file. `encrypted` says whether it is one of the app's encrypted files,
+// as far as its first bytes tell. `output` is only set when the user picked where it is
+// saved.
+pub struct Item {
+    pub path: PathBuf,
+    pub encrypted: bool,
+    pub output: Option<PathBuf>,
+    pub status: ItemStatus,
+}
+
+#[derive(Debug, Clone, Copy)]
+//Summary counts the files in the queue by how they ended. Files that were cancelled or
+// never reached count as remaining.
+pub struct Summary {
+    pub finished: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub remaining: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Queue {
+    items: Vec<Item>,
+}
+
+impl Queue {
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    //Adds files to the end of the queue, skipping any that are already in it.
+    pub fn add(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if !self.items.iter().any(|item| item.path == path) {
+                let encrypted = files::is_encrypted(&path);
+                self.items.push(Item { path, encrypted, output: None, status: ItemStatus::Pending });
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    //Sets where the file at `index` is saved.
+    pub fn set_output(&mut self, index: usize, output: PathBuf) {
+        if let Some(item) = self.items.get_mut(index) {
+            item.output = Some(output);
+        }
+    }
+
+    //Forgets the outputs chosen with set_output, which only make sense for the mode they
+    // were chosen in.
+    pub fn reset_outputs(&mut self) {
+        for item in &mut self.items {
+            item.output = None;
+        }
+    }
+
+    //Gets the queue ready for a new run: files that failed, were skipped or were cancelled
+    // last time are tried again, files that were finished are left alone. Verifying leaves
+    // nothing behind, so verified files are checked again too. Returns how many files the
+    // run will process.
+    pub fn prepare(&mut self) -> usize {
+        for item in &mut self.items {
+            if matches!(item.status, ItemStatus::Failed(_) | ItemStatus::Skipped | ItemStatus::Cancelled | ItemStatus::Verified(_)) {
+                item.status = ItemStatus::Pending;
+            }
+        }
+        self.items.iter().filter(|item| matches!(item.status, ItemStatus::Pending)).count()
+    }
+
+    //Marks the next pending file as running and returns it, or None once every file has
+    // been processed.
+    pub fn start_next(&mut self) -> Option<Item> {
+        let item = self.items.iter_mut().find(|item| matches!(item.status, ItemStatus::Pending))?;
+        item.status = ItemStatus::Running;
+        Some(item.clone())
+    }
+
+    //Returns the position of the running file, counting from 1, for "file 3 of 10".
+    pub fn current(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| matches!(item.status, ItemStatus::Running))
+            .map(|index| index + 1)
+    }
+
+    //Returns the files the next run would process, leaving out those already finished.
+    pub fn unfinished(&self) -> impl Iterator<Item = &Item> {
+        self.items.iter().filter(|item| !matches!(item.status, ItemStatus::Finished(_)))
+    }
+
+    //Records how the running file ended.
+    pub fn finish_current(&mut self, status: ItemStatus) {
+        if let Some(item) = self.items.iter_mut().find(|item| matches!(item.status, ItemStatus::Running)) {
+            item.status = status;
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary { finished: 0, skipped: 0, failed: 0, remaining: 0 };
+        for item in &self.items {
+            match item.status {
+                ItemStatus::Finished(_) | ItemStatus::Verified(_) => summary.finished += 1,
+                ItemStatus::Skipped => summary.skipped += 1,
+                ItemStatus::Failed(_) => summary.failed += 1,
+                _ => summary.remaining += 1,
+            }
+        }
+        summary
+    }
+}
+
+//Lists every file in `dir` and its subfolders, sorted by path so a folder is always
+// processed in the same order.
+pub fn files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+// Symbolic links to folders are not followed, so a link back up the tree cannot make
+// the walk go round in circles; links to files are included.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,503 @@
+/*
+Command-line interface to the same encryption core as the GUI, for scripts, servers and
+CI where there is no display. Files written by one can be read by the other. The
+password is prompted for on the terminal unless it is taken from an environment
+variable, an inherited file descriptor or a file, so it never has to appear on the
+command line (where other users could see it in the process list). Keyfiles can be given
+with --keyfile, in addition to the password or, with --no-password, instead of it.
+Files can also be encrypted to other people's public keys with --recipient, and are then
+decrypted with the matching identity (secret key) file instead of a password. Each
+password and public key is a key slot of the file; add-key and remove-key change them
+without re-encrypting it. Folders are encrypted into a single file, and decrypting that
+file restores the folder. With --compress the data is compressed before it is encrypted.
+The original's name, time and permissions are stored encrypted and restored on
+decrypting; with --no-name the name is not stored at all.
+
+    aes256_encryption_cli encrypt report.pdf
+    aes256_encryption_cli decrypt report.pdf.aes --output-dir restored --password-env PASS
+    aes256_encryption_cli verify report.pdf.aes --password-file secret.txt
+    aes256_encryption_cli info report.pdf.aes
+    aes256_encryption_cli generate-keyfile /media/usb/report.key
+    aes256_encryption_cli encrypt report.pdf --keyfile /media/usb/report.key
+    aes256_encryption_cli generate-identity ~/.config/me.key
+    aes256_encryption_cli encrypt report.pdf -r age1...
+    aes256_encryption_cli decrypt report.pdf.aes -i ~/.config/me.key
+    aes256_encryption_cli add-key report.pdf.aes --new-password-env NEW_PASS
+    aes256_encryption_cli remove-key report.pdf.aes 1
+    aes256_encryption_cli encrypt project/
+    aes256_encryption_cli encrypt server.log --compress zstd:19
+ */
+use aes256_encryption_gui_app::container::{Header, KeySlot, CURRENT_VERSION};
+use aes256_encryption_gui_app::keyslot::{self, NewSlot};
+use aes256_encryption_gui_app::metadata;
+use aes256_encryption_gui_app::naming::DEFAULT_EXTENSION;
+use aes256_encryption_gui_app::recipient::{self, Identity, Recipient};
+use aes256_encryption_gui_app::secret::Zeroizing;
+use aes256_encryption_gui_app::strength::{self, Strength};
+use aes256_encryption_gui_app::{archive, files, keyfile, Compression, DecryptOptions, EncryptOptions, Error, Kdf, OutputNaming};
+use clap::{Args, Parser, Subcommand};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(version, about = "Encrypt and decrypt files with AES-256")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt a file, or a folder into a single file
+    Encrypt {
+        input: PathBuf,
+        /// Where to write the encrypted file [default: INPUT.aes]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        naming: Naming,
+        #[command(flatten)]
+        password: PasswordSource,
+        #[command(flatten)]
+        keyfiles: Keyfiles,
+        #[command(flatten)]
+        recipients: Recipients,
+        /// Overwrite and delete the input once the encrypted file has been checked to decrypt
+        /// to it. Best effort: SSDs and copy-on-write file systems may keep the old data
+        #[arg(long)]
+        remove_original: bool,
+        /// Compress before encrypting: none, deflate or zstd, with an optional level (zstd:19)
+        #[arg(long, value_name = "ALG[:LEVEL]", default_value = "none")]
+        compress: Compression,
+        /// Do not store the original name, not even encrypted. The default output name
+        /// still shows it; choose another with --output
+        #[arg(long)]
+        no_name: bool,
+    },
+    /// Decrypt a file, or restore an encrypted folder
+    Decrypt {
+        input: PathBuf,
+        /// Where to write the decrypted file [default: the file's original name]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        naming: Naming,
+        #[command(flatten)]
+        password: PasswordSource,
+        #[command(flatten)]
+        keyfiles: Keyfiles,
+        #[command(flatten)]
+        identities: Identities,
+    },
+    /// Check that a file decrypts with the password or identity and has not been modified, and show its header
+    Verify {
+        input: PathBuf,
+        #[command(flatten)]
+        password: PasswordSource,
+        #[command(flatten)]
+        keyfiles: Keyfiles,
+        #[command(flatten)]
+        identities: Identities,
+    },
+    /// Show how a file was encrypted and its key slots (no password needed)
+    Info { input: PathBuf },
+    /// Add a password or public key that opens an encrypted file
+    AddKey {
+        input: PathBuf,
+        #[command(flatten)]
+        password: PasswordSource,
+        #[command(flatten)]
+        keyfiles: Keyfiles,
+        #[command(flatten)]
+        identities: Identities,
+        #[command(flatten)]
+        new_key: NewKey,
+    },
+    /// Remove a key slot, numbered as `info` lists them, from an encrypted file
+    RemoveKey {
+        input: PathBuf,
+        slot: usize,
+        #[command(flatten)]
+        password: PasswordSource,
+        #[command(flatten)]
+        keyfiles: Keyfiles,
+        #[command(flatten)]
+        identities: Identities,
+    },
+    /// Write a new keyfile of 32 random bytes
+    GenerateKeyfile { output: PathBuf },
+    /// Write a new identity (secret key) file and print its public key
+    GenerateIdentity { output: PathBuf },
+}
+
+#[derive(Args)]
+//Naming picks the output path when --output is not given, and says whether an existing
+// file at that path may be replaced.
+struct Naming {
+    /// Write the output into this folder instead of next to the input
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+    /// Extension of encrypted files
+    #[arg(long, value_name = "EXT", default_value = DEFAULT_EXTENSION)]
+    extension: String,
+    /// Replace the output file if it already exists
+    #[arg(short, long)]
+    force: bool,
+}
+
+impl From<Naming> for OutputNaming {
+    fn from(naming: Naming) -> Self {
+        OutputNaming { extension: naming.extension, directory: naming.output_dir }
+    }
+}
+
+#[derive(Args)]
+#[group(multiple = false)]
+//PasswordSource says where the password comes from. With none of the options set it is
+// prompted for on the terminal.
+struct PasswordSource {
+    /// Read the password from this environment variable
+    #[arg(long, value_name = "VAR")]
+    password_env: Option<String>,
+    /// Read the password from this open file descriptor (Unix only)
+    #[arg(long, value_name = "FD")]
+    password_fd: Option<i32>,
+    /// Read the password from the first line of this file
+    #[arg(long, value_name = "FILE")]
+    password_file: Option<PathBuf>,
+    /// Use the keyfiles alone, without a password
+    #[arg(long, requires = "keyfiles")]
+    no_password: bool,
+}
+
+#[derive(Args)]
+//Keyfiles are combined with the password. They can be given in any order, but decrypting
+// needs every keyfile the file was encrypted with.
+struct Keyfiles {
+    /// Combine this keyfile with the password (repeat for several)
+    #[arg(long = "keyfile", value_name = "FILE")]
+    keyfiles: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+//Recipients are the public keys a file is encrypted to. Without a password source or
+// keyfiles, they are the only way to open it.
+struct Recipients {
+    /// Encrypt to this public key (age1...) (repeat for several)
+    #[arg(short = 'r', long = "recipient", value_name = "KEY")]
+    recipients: Vec<Recipient>,
+    /// Encrypt to the public keys in this file, one per line (repeat for several)
+    #[arg(short = 'R', long = "recipients-file", value_name = "FILE")]
+    recipients_files: Vec<PathBuf>,
+}
+
+impl Recipients {
+    fn is_empty(&self) -> bool {
+        self.recipients.is_empty() && self.recipients_files.is_empty()
+    }
+
+    fn read(self) -> Result<Vec<Recipient>, String> {
+        let mut recipients = self.recipients;
+        for path in &self.recipients_files {
+            let keys = recipient::read_recipients(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            recipients.extend(keys);
+        }
+        Ok(recipients)
+    }
+}
+
+#[derive(Args)]
+//Identities are the secret keys tried on a file encrypted to recipients.
+struct Identities {
+    /// Decrypt with the secret keys in this identity file (repeat for several)
+    #[arg(short = 'i', long = "identity", value_name = "FILE")]
+    identity_files: Vec<PathBuf>,
+}
+
+impl Identities {
+    // Returns what opening `input` takes: the identities, and the password unless the
+    // identities are used instead. The header is read first, so nobody is prompted for a
+    // password the file does not take. Files from the first release have no header and
+    // only take a password.
+    fn credentials(&self, input: &Path, password: &PasswordSource) -> Result<(Zeroizing<String>, Vec<Identity>), String> {
+        let takes_password = match files::read_header(input) {
+            Ok(header) => header.slots.iter().any(|slot| matches!(slot, KeySlot::Password { .. })),
+            Err(_) if files::is_legacy(input) => true,
+            Err(e) => return Err(format!("cannot read {}: {}", input.display(), e)),
+        };
+        let mut identities = Vec::new();
+        for path in &self.identity_files {
+            let keys = recipient::read_identities(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+            identities.extend(keys);
+        }
+        if !takes_password && identities.is_empty() {
+            return Err(format!("{} is encrypted to recipients only; give an identity file with --identity", input.display()));
+        }
+        if !takes_password || (!identities.is_empty() && !password.is_given()) {
+            return Ok((Zeroizing::default(), identities));
+        }
+        Ok((password.read("Password", false)?, identities))
+    }
+}
+
+#[derive(Args)]
+//NewKey is the key slot add-key adds: a public key, or a new password and keyfiles. The
+// new password is read like the one that opens the file, and prompted for when neither
+// source is given.
+struct NewKey {
+    /// Add this public key (age1...) instead of a password
+    #[arg(short = 'r', long = "recipient", value_name = "KEY", conflicts_with_all = ["new_password_env", "new_password_file", "new_keyfiles", "new_no_password"])]
+    recipient: Option<Recipient>,
+    /// Read the new password from this environment variable
+    #[arg(long, value_name = "VAR", conflicts_with = "new_password_file")]
+    new_password_env: Option<String>,
+    /// Read the new password from the first line of this file
+    #[arg(long, value_name = "FILE")]
+    new_password_file: Option<PathBuf>,
+    /// Combine this keyfile with the new password (repeat for several)
+    #[arg(long = "new-keyfile", value_name = "FILE")]
+    new_keyfiles: Vec<PathBuf>,
+    /// Use the new keyfiles alone, without a new password
+    #[arg(long, requires = "new_keyfiles", conflicts_with_all = ["new_password_env", "new_password_file"])]
+    new_no_password: bool,
+}
+
+impl NewKey {
+    fn password_source(&self) -> PasswordSource {
+        PasswordSource {
+            password_env: self.new_password_env.clone(),
+            password_fd: None,
+            password_file: self.new_password_file.clone(),
+            no_password: self.new_no_password,
+        }
+    }
+}
+
+impl PasswordSource {
+    //Whether the password comes from somewhere other than a prompt.
+    fn is_given(&self) -> bool {
+        self.password_env.is_some() || self.password_fd.is_some() || self.password_file.is_some() || self.no_password
+    }
+
+    //Returns the password, prompting with `prompt` if no source is given. When prompting
+    // for a new password (`confirm`), it is asked for twice, since a typo would make the
+    // file impossible to decrypt. Everything the password was read into is wiped once it
+    // is dropped.
+    fn read(&self, prompt: &str, confirm: bool) -> Result<Zeroizing<String>, String> {
+        if self.no_password {
+            return Ok(Zeroizing::default());
+        }
+        if let Some(var) = &self.password_env {
+            return env::var(var)
+                .map(Zeroizing::new)
+                .map_err(|e| format!("cannot read the password from ${}: {}", var, e));
+        }
+        if let Some(fd) = self.password_fd {
+            return read_fd(fd)
+                .map(|text| first_line(&Zeroizing::new(text)))
+                .map_err(|e| format!("cannot read the password from file descriptor {}: {}", fd, e));
+        }
+        if let Some(path) = &self.password_file {
+            return fs::read_to_string(path)
+                .map(|text| first_line(&Zeroizing::new(text)))
+                .map_err(|e| format!("cannot read the password from {}: {}", path.display(), e));
+        }
+
+        let password = rpassword::prompt_password(format!("{}: ", prompt))
+            .map(Zeroizing::new)
+            .map_err(|e| format!("cannot read the password: {}", e))?;
+        if confirm {
+            let estimate = strength::estimate(&password);
+            if !password.is_empty() && estimate.strength < Strength::Fair {
+                eprintln!("warning: this password is {} (about {:.0} bits)", estimate.strength, estimate.bits);
+            }
+            let again = rpassword::prompt_password(format!("Confirm {}: ", prompt.to_lowercase()))
+                .map(Zeroizing::new)
+                .map_err(|e| format!("cannot read the password: {}", e))?;
+            if again != password {
+                return Err(String::from("the passwords do not match"));
+            }
+        }
+        Ok(password)
+    }
+}
+
+// Passwords read from files usually end with a newline that is not part of the password.
+fn first_line(text: &str) -> Zeroizing<String> {
+    Zeroizing::new(text.lines().next().unwrap_or("").to_string())
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> io::Result<String> {
+    use std::os::unix::io::FromRawFd;
+
+    // SAFETY: the descriptor was handed to us by the caller for this purpose, and nothing
+    // else in the process uses it; the File takes ownership and closes it when dropped.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_fd: i32) -> io::Result<String> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "not supported on this platform"))
+}
+
+fn force_hint(e: &Error) -> &'static str {
+    match e {
+        Error::AlreadyExists(_) => " (use --force to replace it)",
+        _ => "",
+    }
+}
+
+fn info(input: &Path) -> Result<(), Error> {
+    let header = files::read_header(input)?;
+    print_header(&header);
+    Ok(())
+}
+
+// Prints what a header says about a file, for info and after a file has been verified.
+fn print_header(header: &Header) {
+    println!("format version: {}", CURRENT_VERSION);
+    println!("cipher:         {}", header.cipher);
+    if let Some(created) = header.created {
+        println!("encrypted on:   {}", metadata::format_time(created));
+    }
+    println!("key slots:      {}", header.slots.len());
+    for (i, slot) in header.slots.iter().enumerate() {
+        match slot {
+            KeySlot::Password { kdf, .. } => println!("  {}: {}, key derived with {}", i + 1, slot, kdf),
+            KeySlot::Recipient(_) => println!("  {}: {}", i + 1, slot),
+        }
+    }
+    println!("chunk size:     {} bytes", header.chunk_size);
+    if header.archive {
+        println!("contents:       a folder");
+    }
+    if header.compression != Compression::None {
+        println!("compression:    {}", header.compression);
+    }
+    if let Some(size) = header.file_size {
+        println!("original size:  {} bytes", size);
+    }
+    if header.metadata.is_some() {
+        println!("metadata:       encrypted (the original's name if stored, time and permissions)");
+    }
+}
+
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Encrypt { input, output, naming, password, keyfiles, recipients, remove_original, compress, no_name } => {
+            let options = EncryptOptions {
+                overwrite: naming.force,
+                remove_original,
+                compression: compress,
+                store_name: !no_name,
+                keyfiles: keyfiles.keyfiles,
+                recipients: if recipients.is_empty() { Vec::new() } else { recipients.read()? },
+                ..EncryptOptions::default()
+            };
+            let output = output.unwrap_or_else(|| OutputNaming::from(naming).encrypted_path(&input));
+            // With recipients, a password is only used when one is asked for.
+            let password = if options.recipients.is_empty() || password.is_given() {
+                password.read("Password", true)?
+            } else {
+                Zeroizing::default()
+            };
+            let encrypted = if input.is_dir() {
+                archive::encrypt_folder(&input, &output, &password, &options, &mut |_| true)
+            } else {
+                files::encrypt_file(&input, &output, &password, &options, &mut |_| true)
+            };
+            encrypted.map_err(|e| format!("cannot encrypt {}: {}{}", input.display(), e, force_hint(&e)))?;
+            eprintln!("encrypted file saved as: {}", output.display());
+            if remove_original {
+                eprintln!("removed the original: {}", input.display());
+            }
+        }
+        Command::Decrypt { input, output, naming, password, keyfiles, identities } => {
+            let (password, identities) = identities.credentials(&input, &password)?;
+            let options = DecryptOptions {
+                overwrite: naming.force,
+                keyfiles: keyfiles.keyfiles,
+                identities,
+                ..DecryptOptions::default()
+            };
+            // Without -o the output is named after the original, which is only known once
+            // the file has been opened.
+            let naming = OutputNaming::from(naming);
+            let mut output_for = |name: Option<&str>| Ok(naming.decrypted_path(&input, name));
+            let folder = files::read_header(&input).is_ok_and(|header| header.archive);
+            let decrypted = match output {
+                Some(output) if folder => archive::decrypt_folder(&input, &output, &password, &options, &mut |_| true).map(|()| output),
+                Some(output) => files::decrypt_file(&input, &output, &password, &options, &mut |_| true).map(|()| output),
+                None if folder => archive::decrypt_folder_named(&input, &password, &options, &mut output_for, &mut |_| true),
+                None => files::decrypt_file_named(&input, &password, &options, &mut output_for, &mut |_| true),
+            };
+            let output = decrypted.map_err(|e| format!("cannot decrypt {}: {}{}", input.display(), e, force_hint(&e)))?;
+            eprintln!("decrypted {} saved as: {}", if folder { "folder" } else { "file" }, output.display());
+            if files::is_legacy(&input) {
+                eprintln!("warning: {}", files::LEGACY_WARNING);
+            }
+        }
+        Command::Verify { input, password, keyfiles, identities } => {
+            let (password, identities) = identities.credentials(&input, &password)?;
+            let options = DecryptOptions { keyfiles: keyfiles.keyfiles, identities, ..DecryptOptions::default() };
+            let header = files::verify_file(&input, &password, &options, &mut |_| true)
+                .map_err(|e| format!("{} did not verify: {}", input.display(), e))?;
+            eprintln!("{} is intact and the key is correct", input.display());
+            print_header(&header);
+        }
+        Command::Info { input } => {
+            info(&input).map_err(|e| format!("cannot read {}: {}", input.display(), e))?;
+        }
+        Command::AddKey { input, password, keyfiles, identities, new_key } => {
+            let (password, identities) = identities.credentials(&input, &password)?;
+            let options = DecryptOptions { keyfiles: keyfiles.keyfiles, identities, ..DecryptOptions::default() };
+            let new_password;
+            let slot = match new_key.recipient {
+                Some(recipient) => NewSlot::Recipient(recipient),
+                None => {
+                    new_password = new_key.password_source().read("New password", true)?;
+                    NewSlot::Password { password: &new_password, keyfiles: &new_key.new_keyfiles, kdf: Kdf::default() }
+                }
+            };
+            let header = keyslot::add_slot(&input, &password, &options, &slot)
+                .map_err(|e| format!("cannot add a key to {}: {}", input.display(), e))?;
+            eprintln!("added key slot {} to {}", header.slots.len(), input.display());
+        }
+        Command::RemoveKey { input, slot, password, keyfiles, identities } => {
+            let index = slot.checked_sub(1).ok_or("key slots are numbered from 1")?;
+            let (password, identities) = identities.credentials(&input, &password)?;
+            let options = DecryptOptions { keyfiles: keyfiles.keyfiles, identities, ..DecryptOptions::default() };
+            keyslot::remove_slot(&input, &password, &options, index)
+                .map_err(|e| format!("cannot remove a key from {}: {}", input.display(), e))?;
+            eprintln!("removed key slot {} from {}", slot, input.display());
+        }
+        Command::GenerateKeyfile { output } => {
+            keyfile::generate(&output).map_err(|e| format!("cannot create the keyfile: {}", e))?;
+            eprintln!("keyfile saved as: {}", output.display());
+        }
+        Command::GenerateIdentity { output } => {
+            let identity = Identity::generate();
+            recipient::write_identity(&output, &identity).map_err(|e| format!("cannot create the identity: {}", e))?;
+            eprintln!("identity saved as: {}; share this public key:", output.display());
+            println!("{}", identity.recipient());
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,89 @@
+/*
+Payload encryption for each Cipher in the container header. New files are encrypted
+with an AEAD (AES-256-GCM by default, or ChaCha20-Poly1305), which authenticates the
+ciphertext together with the header bytes it was written with: a flipped bit anywhere
+in the file, or a wrong password, is detected before the affected plaintext is used.
+The chunking of large files on top of the AEAD lives in stream.rs. AES-256-CBC is only
+kept so files written by the first release, before authenticated encryption was added,
+can still be decrypted.
+ */
+use crate::container::Cipher;
+use crate::kdf::KEY_LEN;
+use aes::{Aes256, NewBlockCipher};
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::Aes256Gcm;
+use block_modes::block_padding::Pkcs7;
+use block_modes::{BlockMode, Cbc};
+use chacha20poly1305::ChaCha20Poly1305;
+use generic_array::GenericArray;
+use std::error;
+use std::fmt;
+
+//The cipher used for new files.
+pub const DEFAULT_CIPHER: Cipher = Cipher::Aes256Gcm;
+
+//Length of the authentication tag both AEADs append to every message.
+pub const TAG_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//AuthenticationError is returned when the ciphertext does not verify. Authentication
+// cannot tell a wrong password apart from a modified file, so the message names both.
+pub struct AuthenticationError;
+
+impl fmt::Display for AuthenticationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the file was modified or the password is wrong")
+    }
+}
+
+impl error::Error for AuthenticationError {}
+
+//AeadCipher is an AEAD instance keyed for one file. It hides which of the supported
+// algorithms is in use from the code that seals and opens the payload. The AES-GCM
+// state holds the expanded key schedule and is boxed to keep the enum small.
+pub enum AeadCipher {
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+impl AeadCipher {
+    pub fn new(cipher: Cipher, key: &[u8; KEY_LEN]) -> AeadCipher {
+        let key = GenericArray::from_slice(key);
+        match cipher {
+            Cipher::Aes256Gcm => AeadCipher::Aes256Gcm(Box::new(Aes256Gcm::new(key))),
+            Cipher::ChaCha20Poly1305 => AeadCipher::ChaCha20Poly1305(ChaCha20Poly1305::new(key)),
+        }
+    }
+
+    //Encrypts `msg` and appends the tag; `aad` is authenticated but not encrypted.
+    pub fn seal(&self, nonce: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+        let nonce = GenericArray::from_slice(nonce);
+        let payload = Payload { msg, aad };
+        match self {
+            AeadCipher::Aes256Gcm(c) => c.encrypt(nonce, payload),
+            AeadCipher::ChaCha20Poly1305(c) => c.encrypt(nonce, payload),
+        }
+        .expect("message is within the AEAD length limit")
+    }
+
+    //Verifies and decrypts what seal produced with the same nonce and aad.
+    pub fn open(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, AuthenticationError> {
+        let nonce = GenericArray::from_slice(nonce);
+        let payload = Payload { msg: ciphertext, aad };
+        match self {
+            AeadCipher::Aes256Gcm(c) => c.decrypt(nonce, payload),
+            AeadCipher::ChaCha20Poly1305(c) => c.decrypt(nonce, payload),
+        }
+        .map_err(|_| AuthenticationError)
+    }
+}
+
+//Decrypts a file written by the first release: AES-256-CBC with PKCS7 padding and an
+// all-zero IV, with no header. CBC is not authenticated; the only check available is the
+// padding, which catches most but not all wrong passwords and modified files.
+pub fn decrypt_legacy(key: &[u8; KEY_LEN], ciphertext: &[u8]) -> Result<Vec<u8>, AuthenticationError> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    Cbc::<Aes256, Pkcs7>::new(cipher, &Default::default())
+        .decrypt_vec(ciphertext)
+        .map_err(|_| AuthenticationError)
+}
@@ -0,0 +1,176 @@
+/*
+Compressing the plaintext before it is encrypted. Encrypted data looks random and cannot
+be compressed afterwards, so logs, CSV exports and other text take far less space when
+they are compressed first. zstd is fast and compresses well; deflate (as in zip and gzip)
+is slower and compresses less, but is offered as well. The algorithm and its level are
+stored in the header (see container.rs), so decrypting undoes the compression without
+being told; the level is only needed for showing how a file was made.
+
+Compression is off unless asked for. The size of compressed data depends on what it
+contains, so someone who sees the encrypted files and can get text of their own into the
+plaintext may learn something about the rest from how well it compresses, as in the
+CRIME attack on TLS.
+ */
+use crate::error::Error;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+// Identifiers of the algorithms in the header. They are part of the file format.
+const DEFLATE_ID: u8 = 1;
+const ZSTD_ID: u8 = 2;
+
+//Levels used when only the algorithm is chosen.
+pub const DEFAULT_DEFLATE_LEVEL: u8 = 6;
+pub const DEFAULT_ZSTD_LEVEL: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+//Compression is the algorithm the plaintext is compressed with before encryption, with
+// its level: 0 (stored) to 9 for deflate, 1 to 22 for zstd. Higher levels compress
+// better and take longer.
+pub enum Compression {
+    #[default]
+    None,
+    Deflate(u8),
+    Zstd(u8),
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Deflate(level) => write!(f, "deflate level {}", level),
+            Compression::Zstd(level) => write!(f, "zstd level {}", level),
+        }
+    }
+}
+
+//Parses "none", "deflate" or "zstd", optionally followed by a level, as in "zstd:19".
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let (name, level) = match text.split_once(':') {
+            Some((name, level)) => {
+                let level = level.parse().map_err(|_| Error::InvalidOptions(format!("invalid compression level {}", level)))?;
+                (name, Some(level))
+            }
+            None => (text, None),
+        };
+        let compression = match (name.to_ascii_lowercase().as_str(), level) {
+            ("none", None) => Compression::None,
+            ("deflate", level) => Compression::Deflate(level.unwrap_or(DEFAULT_DEFLATE_LEVEL)),
+            ("zstd", level) => Compression::Zstd(level.unwrap_or(DEFAULT_ZSTD_LEVEL)),
+            _ => return Err(Error::InvalidOptions(format!("unknown compression {}; use none, deflate or zstd", text))),
+        };
+        compression.validate().map_err(|e| Error::InvalidOptions(e.to_string()))?;
+        Ok(compression)
+    }
+}
+
+impl Compression {
+    //The levels the algorithm accepts; None has only level 0.
+    pub fn levels(&self) -> RangeInclusive<u8> {
+        match self {
+            Compression::None => 0..=0,
+            Compression::Deflate(_) => 0..=9,
+            Compression::Zstd(_) => 1..=22,
+        }
+    }
+
+    //Serializes the algorithm identifier followed by the level. None is never written.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match *self {
+            Compression::None => Err(io::Error::new(io::ErrorKind::InvalidInput, "no compression to write")),
+            Compression::Deflate(level) => out.write_all(&[DEFLATE_ID, level]),
+            Compression::Zstd(level) => out.write_all(&[ZSTD_ID, level]),
+        }
+    }
+
+    //Parses what write_to produced. Unknown identifiers and levels are reported as
+    // InvalidData.
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Compression> {
+        let mut bytes = [0u8; 2];
+        input.read_exact(&mut bytes)?;
+        let compression = match bytes {
+            [DEFLATE_ID, level] => Compression::Deflate(level),
+            [ZSTD_ID, level] => Compression::Zstd(level),
+            [other, _] => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown compression id {}", other)));
+            }
+        };
+        compression.validate()?;
+        Ok(compression)
+    }
+
+    //Checks that the level is one the algorithm accepts.
+    pub fn validate(&self) -> io::Result<()> {
+        let level = match *self {
+            Compression::None => 0,
+            Compression::Deflate(level) | Compression::Zstd(level) => level,
+        };
+        if self.levels().contains(&level) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported compression: {}", self)))
+        }
+    }
+}
+
+//Encoder compresses everything written to it into the inner writer. finish must be called
+// at the end, since the compressors hold back data until they are told it is complete.
+pub(crate) enum Encoder<W: Write> {
+    Stored(W),
+    Deflate(DeflateEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(inner: W, compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Encoder::Stored(inner),
+            Compression::Deflate(level) => Encoder::Deflate(DeflateEncoder::new(inner, flate2::Compression::new(level.into()))),
+            Compression::Zstd(level) => Encoder::Zstd(zstd::stream::write::Encoder::new(inner, level.into())?),
+        })
+    }
+
+    //Writes out what the compressor still holds and returns the inner writer.
+    pub(crate) fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Stored(inner) => Ok(inner),
+            Encoder::Deflate(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Stored(inner) => inner.write(buf),
+            Encoder::Deflate(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Stored(inner) => inner.flush(),
+            Encoder::Deflate(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+//Returns a reader that decompresses what it reads from `inner`. It may stop before the
+// end of `inner` once the compressed data is complete.
+pub(crate) fn decoder<'a, R: Read + 'a>(inner: R, compression: Compression) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match compression {
+        Compression::None => Box::new(inner),
+        Compression::Deflate(_) => Box::new(DeflateDecoder::new(inner)),
+        Compression::Zstd(_) => Box::new(zstd::stream::read::Decoder::new(inner)?),
+    })
+}
@@ -0,0 +1,437 @@
+/*
+The encrypted file container. Every file written by the app starts with a header that
+describes how to decrypt it, followed by the ciphertext:
+
+    magic      8 bytes   "A256CRYP"
+    version    1 byte    format version, 2
+    cipher     1 byte    cipher/mode identifier (see Cipher)
+    fields     tag (1 byte), length (u16 LE), value; repeated
+    end        1 byte    tag 0
+
+Fields are tagged so new ones can be added without moving the existing ones around.
+A reader that meets a tag it does not know refuses the file instead of guessing,
+because skipping a field could silently change how the payload has to be decrypted.
+All integers are little-endian. The header bytes, exactly as stored, are authenticated
+together with the payload, except for the key slot fields: each of those is sealed on
+its own (see keyslot.rs), and leaving them out means slots can later be added to or
+removed from a file without touching its payload.
+
+Files are protected by a random file key stored in one or more key slots, each opened by
+a password (and keyfiles) or by a recipient's secret key. A password slot field holds
+the number of keyfiles (1 byte), the salt length (1 byte), the salt, the wrapped file key
+and the KDF parameters, in that order. An empty archive
+field marks a payload that is a whole folder packed as a tar archive (see archive.rs)
+rather than the contents of a single file. The compression field holds the algorithm
+and level the plaintext was compressed with (see compression.rs); without one it was not.
+The file size field holds the size of the original file (u64), which decrypting checks
+the plaintext against; it is in the clear, like the size of the payload that would give
+it away anyway. The metadata field holds the original file's name, time and
+permissions, sealed under the file key (see metadata.rs), so only those who can open the
+file can read them. The header names nothing in the clear. The created field holds when the file was encrypted, in seconds since 1970 (u64); it is
+there to be shown, and anyone can read it, as they can the times of the file itself.
+
+Every file carries a chunk size and a nonce prefix, and the payload is a sequence of
+chunks as described in stream.rs. There is a single format version: a field added later
+gets a tag of its own, which readers that do not know it refuse, so it needs no new
+version. Only a change to the layout above would.
+ */
+use crate::cipher::TAG_LEN;
+use crate::compression::Compression;
+use crate::kdf::{Kdf, KEY_LEN};
+use crate::stream::{MAX_CHUNK_SIZE, NONCE_PREFIX_LEN};
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const MAGIC: &[u8; 8] = b"A256CRYP";
+pub const CURRENT_VERSION: u8 = 2;
+
+const TAG_END: u8 = 0;
+const TAG_NONCE: u8 = 3;
+const TAG_FILE_SIZE: u8 = 5;
+const TAG_CHUNK_SIZE: u8 = 6;
+const TAG_RECIPIENT: u8 = 8;
+const TAG_PASSWORD_SLOT: u8 = 9;
+const TAG_ARCHIVE: u8 = 10;
+const TAG_COMPRESSION: u8 = 11;
+const TAG_METADATA: u8 = 12;
+const TAG_CREATED: u8 = 13;
+
+//Length of an X25519 public key.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//Cipher identifies the algorithm and mode the payload was encrypted with. The numeric
+// values are part of the file format and must never be reused for something else. Files
+// written by the first release, with AES-256-CBC, have no header at all (see files.rs).
+pub enum Cipher {
+    Aes256Gcm = 2,
+    ChaCha20Poly1305 = 3,
+}
+
+impl Cipher {
+    fn from_id(id: u8) -> Option<Cipher> {
+        match id {
+            2 => Some(Cipher::Aes256Gcm),
+            3 => Some(Cipher::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cipher::Aes256Gcm => write!(f, "AES-256-GCM"),
+            Cipher::ChaCha20Poly1305 => write!(f, "ChaCha20-Poly1305"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+//KeySlot is the file key wrapped for one way of opening the file (see keyslot.rs).
+pub enum KeySlot {
+    //Wrapped under a key derived from a password and `keyfiles` keyfiles with the KDF and
+    // salt.
+    Password { kdf: Kdf, salt: Vec<u8>, keyfiles: u8, wrapped: Vec<u8> },
+    //Wrapped for a recipient's public key.
+    Recipient(Stanza),
+}
+
+impl fmt::Display for KeySlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySlot::Password { keyfiles: 0, .. } => write!(f, "password"),
+            KeySlot::Password { keyfiles: 1, .. } => write!(f, "password and 1 keyfile"),
+            KeySlot::Password { keyfiles, .. } => write!(f, "password and {} keyfiles", keyfiles),
+            KeySlot::Recipient(_) => write!(f, "public key"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+//Stanza is the file key wrapped for one recipient (see recipient.rs): the ephemeral public
+// key it was wrapped with, followed in the file by the sealed key.
+pub struct Stanza {
+    pub ephemeral: [u8; PUBLIC_KEY_LEN],
+    pub wrapped: Vec<u8>,
+}
+
+//Length of a wrapped file key: the sealed key and its tag.
+pub const WRAPPED_KEY_LEN: usize = KEY_LEN + TAG_LEN;
+
+// Length of a stanza as stored: the ephemeral key and the wrapped file key.
+const STANZA_LEN: usize = PUBLIC_KEY_LEN + WRAPPED_KEY_LEN;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+//Header holds everything needed to decrypt a file apart from the password or identity:
+// the cipher, the key slots that hold the file key, the 7 byte STREAM nonce prefix, the
+// chunk size and the size of the original file, if it had one. With `archive` the
+// payload is a folder. The
+// plaintext may be compressed before it is encrypted. `metadata` is the sealed Metadata
+// of the original, with its name, which only the file key opens (see metadata.rs).
+// `created` is when the file was encrypted, to the second, if it was recorded.
+pub struct Header {
+    pub cipher: Cipher,
+    pub slots: Vec<KeySlot>,
+    pub nonce: Vec<u8>,
+    pub chunk_size: u32,
+    pub file_size: Option<u64>,
+    pub archive: bool,
+    pub compression: Compression,
+    pub metadata: Option<Vec<u8>>,
+    pub created: Option<SystemTime>,
+}
+
+impl Header {
+    //Writes the magic, the version and cipher prefix and all fields.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.write_fields(out, true)
+    }
+
+    //Returns the bytes of the header that are authenticated together with the payload:
+    // what write_to writes, without the key slot fields.
+    pub fn authenticated_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_fields(&mut bytes, false)?;
+        Ok(bytes)
+    }
+
+    fn write_fields<W: Write>(&self, out: &mut W, with_slots: bool) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[CURRENT_VERSION, self.cipher as u8])?;
+
+        if with_slots {
+            for slot in &self.slots {
+                match slot {
+                    KeySlot::Password { kdf, salt, keyfiles, wrapped } => {
+                        let salt_len = u8::try_from(salt.len())
+                            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "salt is too long"))?;
+                        let mut value = vec![*keyfiles, salt_len];
+                        value.extend_from_slice(salt);
+                        value.extend_from_slice(wrapped);
+                        kdf.write_to(&mut value)?;
+                        write_field(out, TAG_PASSWORD_SLOT, &value)?;
+                    }
+                    KeySlot::Recipient(stanza) => {
+                        write_field(out, TAG_RECIPIENT, &[&stanza.ephemeral[..], &stanza.wrapped].concat())?;
+                    }
+                }
+            }
+        }
+        write_field(out, TAG_NONCE, &self.nonce)?;
+        write_field(out, TAG_CHUNK_SIZE, &self.chunk_size.to_le_bytes())?;
+        if let Some(size) = self.file_size {
+            write_field(out, TAG_FILE_SIZE, &size.to_le_bytes())?;
+        }
+        if self.archive {
+            write_field(out, TAG_ARCHIVE, &[])?;
+        }
+        if self.compression != Compression::None {
+            let mut value = Vec::new();
+            self.compression.write_to(&mut value)?;
+            write_field(out, TAG_COMPRESSION, &value)?;
+        }
+        if let Some(sealed) = &self.metadata {
+            write_field(out, TAG_METADATA, sealed)?;
+        }
+        if let Some(created) = self.created {
+            let seconds = created.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            write_field(out, TAG_CREATED, &seconds.to_le_bytes())?;
+        }
+        out.write_all(&[TAG_END])
+    }
+
+    //Reads and validates a header, leaving the reader positioned at the first byte of
+    // the payload. The header bytes the payload is authenticated against are returned as
+    // well, exactly as they were read.
+    pub fn read_from<R: Read>(input: &mut R) -> Result<(Header, Vec<u8>), FormatError> {
+        let mut recorder = Recorder { inner: input, bytes: Vec::new() };
+        let header = Header::parse(&mut recorder)?;
+        Ok((header, recorder.bytes))
+    }
+
+    fn parse<R: Read>(input: &mut Recorder<R>) -> Result<Header, FormatError> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(FormatError::NotEncrypted);
+        }
+
+        let mut prefix = [0u8; 2];
+        input.read_exact(&mut prefix)?;
+        let version = prefix[0];
+        if version != CURRENT_VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        let cipher = Cipher::from_id(prefix[1]).ok_or(FormatError::UnknownCipher(prefix[1]))?;
+
+        let mut nonce = None;
+        let mut chunk_size = None;
+        let mut file_size = None;
+        let mut slots = Vec::new();
+        let mut archive = false;
+        let mut compression = Compression::None;
+        let mut metadata = None;
+        let mut created = None;
+        loop {
+            let field_start = input.bytes.len();
+            let mut tag = [0u8; 1];
+            input.read_exact(&mut tag)?;
+            if tag[0] == TAG_END {
+                break;
+            }
+            let value = read_field_value(input)?;
+            match tag[0] {
+                TAG_NONCE => nonce = Some(value),
+                TAG_FILE_SIZE => file_size = Some(u64_field(&value, "file size")?),
+                TAG_CHUNK_SIZE => {
+                    let bytes: [u8; 4] = value
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| FormatError::Invalid("chunk size must be 4 bytes".into()))?;
+                    chunk_size = Some(u32::from_le_bytes(bytes));
+                }
+                TAG_RECIPIENT => {
+                    if value.len() != STANZA_LEN {
+                        return Err(FormatError::Invalid(format!("recipient fields must be {} bytes", STANZA_LEN)));
+                    }
+                    let (ephemeral, wrapped) = value.split_at(PUBLIC_KEY_LEN);
+                    slots.push(KeySlot::Recipient(Stanza {
+                        ephemeral: ephemeral.try_into().expect("split at the key length"),
+                        wrapped: wrapped.to_vec(),
+                    }));
+                    // Not authenticated with the payload; see the top of this file.
+                    input.bytes.truncate(field_start);
+                }
+                TAG_PASSWORD_SLOT => {
+                    slots.push(password_slot(&value)?);
+                    input.bytes.truncate(field_start);
+                }
+                TAG_ARCHIVE => {
+                    if !value.is_empty() {
+                        return Err(FormatError::Invalid("the archive field must be empty".into()));
+                    }
+                    archive = true;
+                }
+                TAG_COMPRESSION => {
+                    compression = Compression::read_from(&mut value.as_slice())
+                        .map_err(|e| FormatError::Invalid(e.to_string()))?;
+                }
+                TAG_METADATA => metadata = Some(value),
+                TAG_CREATED => {
+                    let seconds = u64_field(&value, "creation time")?;
+                    created = Some(
+                        UNIX_EPOCH
+                            .checked_add(Duration::from_secs(seconds))
+                            .ok_or_else(|| FormatError::Invalid("creation time is out of range".into()))?,
+                    );
+                }
+                other => return Err(FormatError::UnknownField(other)),
+            }
+        }
+
+        if slots.is_empty() {
+            return Err(FormatError::MissingField("key slots"));
+        }
+        let nonce = nonce.ok_or(FormatError::MissingField("nonce prefix"))?;
+        if nonce.len() != NONCE_PREFIX_LEN {
+            return Err(FormatError::Invalid(format!(
+                "the nonce prefix must be {} bytes, found {}",
+                NONCE_PREFIX_LEN,
+                nonce.len()
+            )));
+        }
+        let chunk_size = chunk_size.ok_or(FormatError::MissingField("chunk size"))?;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(FormatError::Invalid(format!("unsupported chunk size {}", chunk_size)));
+        }
+
+        Ok(Header {
+            cipher,
+            slots,
+            nonce,
+            chunk_size,
+            file_size,
+            archive,
+            compression,
+            metadata,
+            created,
+        })
+    }
+}
+
+#[derive(Debug)]
+//FormatError explains why a file could not be read as a container. The messages are
+// meant to be shown to the user as they are.
+pub enum FormatError {
+    Io(io::Error),
+    NotEncrypted,
+    Truncated,
+    UnsupportedVersion(u8),
+    UnknownCipher(u8),
+    UnknownField(u8),
+    MissingField(&'static str),
+    Invalid(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Io(e) => write!(f, "failed to read the file header: {}", e),
+            FormatError::NotEncrypted => write!(f, "the file was not encrypted by this app"),
+            FormatError::Truncated => write!(f, "the file header is truncated"),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {} (this app reads version {}); \
+                 the file was probably written by a newer release",
+                version, CURRENT_VERSION
+            ),
+            FormatError::UnknownCipher(id) => write!(f, "unknown cipher id {}", id),
+            FormatError::UnknownField(tag) => write!(
+                f,
+                "unknown header field {}; the file was probably written by a newer release",
+                tag
+            ),
+            FormatError::MissingField(name) => write!(f, "the file header has no {}", name),
+            FormatError::Invalid(reason) => write!(f, "invalid file header: {}", reason),
+        }
+    }
+}
+
+impl error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            FormatError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FormatError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            FormatError::Truncated
+        } else {
+            FormatError::Io(e)
+        }
+    }
+}
+
+// Passes reads through while keeping a copy of every byte, so the header can be
+// authenticated exactly as it appears in the file.
+struct Recorder<'a, R: Read> {
+    inner: &'a mut R,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+fn write_field<W: Write>(out: &mut W, tag: u8, value: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(value.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "header field is too long"))?;
+    out.write_all(&[tag])?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(value)
+}
+
+fn read_field_value<R: Read>(input: &mut R) -> Result<Vec<u8>, FormatError> {
+    let mut len = [0u8; 2];
+    input.read_exact(&mut len)?;
+    let mut value = vec![0u8; u16::from_le_bytes(len) as usize];
+    input.read_exact(&mut value)?;
+    Ok(value)
+}
+
+// Parses the value of a password slot field, laid out as described at the top of this file.
+fn password_slot(value: &[u8]) -> Result<KeySlot, FormatError> {
+    let invalid = || FormatError::Invalid("malformed password slot".into());
+    let [keyfiles, salt_len, rest @ ..] = value else {
+        return Err(invalid());
+    };
+    let salt_len = usize::from(*salt_len);
+    if rest.len() < salt_len + WRAPPED_KEY_LEN {
+        return Err(invalid());
+    }
+    let (salt, rest) = rest.split_at(salt_len);
+    let (wrapped, mut params) = rest.split_at(WRAPPED_KEY_LEN);
+    let kdf = Kdf::read_from(&mut params).map_err(|e| FormatError::Invalid(e.to_string()))?;
+    if !params.is_empty() {
+        return Err(invalid());
+    }
+    Ok(KeySlot::Password { kdf, salt: salt.to_vec(), keyfiles: *keyfiles, wrapped: wrapped.to_vec() })
+}
+
+fn u64_field(value: &[u8], name: &str) -> Result<u64, FormatError> {
+    let bytes: [u8; 8] = value
+        .try_into()
+        .map_err(|_| FormatError::Invalid(format!("{} must be 8 bytes", name)))?;
+    Ok(u64::from_le_bytes(bytes))
+}
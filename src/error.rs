@@ -0,0 +1,136 @@
+/*
+The errors an encryption or decryption can end with. They are meant to be shown to the
+user, so each variant stands for something the user can act on (a wrong password, a
+missing file, a full disk) rather than for the place in the code where it happened.
+ */
+use crate::cipher::AuthenticationError;
+use crate::container::FormatError;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+//Error is Clone so it can travel inside GUI messages; the underlying errors, which are
+// not, are shared behind an Arc.
+pub enum Error {
+    Io(Arc<io::Error>),
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    OutputIsInput(PathBuf),
+    AlreadyExists(PathBuf),
+    DiskFull,
+    Authentication,
+    EmptyPassword,
+    KeyfileCount { needed: usize, given: usize },
+    InvalidKey(String),
+    NoMatchingIdentity,
+    VerificationFailed,
+    SizeMismatch { expected: u64, found: u64 },
+    UnsafeArchivePath(PathBuf),
+    OriginalKept(Box<Error>),
+    NotVerified(Box<Error>),
+    UnsupportedFormat(Arc<FormatError>),
+    InvalidOptions(String),
+    Cancelled,
+}
+
+impl Error {
+    //Converts an I/O error that happened while opening or creating `path`, keeping the
+    // path for the errors where it tells the user what to fix.
+    pub fn with_path(e: io::Error, path: &Path) -> Error {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound(path.to_path_buf()),
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(path.to_path_buf()),
+            _ => Error::from(e),
+        }
+    }
+
+    //Wraps the error in an io::Error, for code that has to fail through the io::Read
+    // or io::Write traits. From<io::Error> unwraps it again.
+    pub fn into_io(self) -> io::Error {
+        io::Error::other(self)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            Error::PermissionDenied(path) => write!(f, "permission denied for {}", path.display()),
+            Error::OutputIsInput(path) => write!(f, "the output {} is the input file itself", path.display()),
+            Error::AlreadyExists(path) => write!(f, "{} already exists", path.display()),
+            Error::DiskFull => write!(f, "there is not enough space left on the disk"),
+            Error::Authentication => write!(f, "{}", AuthenticationError),
+            Error::EmptyPassword => write!(f, "the password is empty and no keyfile was selected"),
+            Error::KeyfileCount { needed: 0, .. } => write!(f, "the file was encrypted without keyfiles"),
+            Error::KeyfileCount { needed: 1, given } => write!(f, "the file needs 1 keyfile, {} selected", given),
+            Error::KeyfileCount { needed, given } => write!(f, "the file needs {} keyfiles, {} selected", needed, given),
+            Error::InvalidKey(reason) => write!(f, "invalid key: {}", reason),
+            Error::NoMatchingIdentity => write!(f, "the file is encrypted to recipients, and none of the selected identities is one of them"),
+            Error::VerificationFailed => write!(f, "the encrypted file did not decrypt to the original, so it was not saved and the original was kept"),
+            Error::SizeMismatch { expected, found } => write!(f, "the decrypted data is {} bytes, but the file recorded {}", found, expected),
+            Error::UnsafeArchivePath(path) => write!(f, "the archive holds {}, which would be extracted outside the output folder", path.display()),
+            Error::OriginalKept(e) => write!(f, "the file was encrypted, but the original could not be removed: {}", e),
+            Error::NotVerified(e) => write!(f, "the file was encrypted, but could not be checked against the original, so both were kept: {}", e),
+            Error::UnsupportedFormat(e) => write!(f, "{}", e),
+            Error::InvalidOptions(reason) => write!(f, "invalid options: {}", reason),
+            Error::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e.as_ref()),
+            Error::UnsupportedFormat(e) => Some(e.as_ref()),
+            Error::OriginalKept(e) | Error::NotVerified(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+//Errors raised inside readers and writers reach us as io::Error, with our own error
+// (or an AuthenticationError from the stream decryptor) inside, possibly wrapped again
+// by a library in between (such as tar); those are unwrapped first, then the error kinds
+// with a dedicated variant are picked out.
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        let mut inner: Option<&(dyn error::Error + 'static)> = e.get_ref().map(|inner| inner as _);
+        while let Some(error) = inner {
+            if let Some(error) = error.downcast_ref::<Error>() {
+                return error.clone();
+            }
+            if error.is::<AuthenticationError>() {
+                return Error::Authentication;
+            }
+            // io::Error::source skips the error it wraps, so that is taken out directly.
+            inner = match error.downcast_ref::<io::Error>() {
+                Some(e) => e.get_ref().map(|inner| inner as _),
+                None => error.source(),
+            };
+        }
+        match e.kind() {
+            io::ErrorKind::StorageFull => Error::DiskFull,
+            _ => Error::Io(Arc::new(e)),
+        }
+    }
+}
+
+impl From<FormatError> for Error {
+    fn from(e: FormatError) -> Self {
+        match e {
+            FormatError::Io(e) => Error::from(e),
+            e => Error::UnsupportedFormat(Arc::new(e)),
+        }
+    }
+}
+
+impl From<AuthenticationError> for Error {
+    fn from(_: AuthenticationError) -> Self {
+        Error::Authentication
+    }
+}
@@ -0,0 +1,643 @@
+/*
+Encrypting and decrypting whole files and streams. Both directions stream the data
+through the chunked AEAD in stream.rs, so memory use does not grow with the file size.
+The file functions report how many bytes of the input have been read through a progress
+callback, which can also cancel the operation by returning false. Output files are
+written to a temporary file in the same folder, flushed to disk and only then renamed to
+their final name, so a failed, cancelled or interrupted operation never leaves a partial
+file under that name; an existing file is only replaced when the options allow it.
+When asked to, encrypt_file removes the original afterwards, but only once the encrypted
+file has been decrypted again and found to match it. The original's time and
+permissions are stored encrypted (see metadata.rs), together with its name, which the
+output of decrypt_file_named is named after once the key has been found; decrypting gives
+the time and permissions back to the output and checks that the plaintext has the size
+the header records. Files written by the first release of the app, which have no header, are
+decrypted too unless DecryptOptions say otherwise.
+ */
+use crate::cipher;
+use crate::compression::{self, Compression};
+use crate::container::{self, Cipher, Header, KeySlot};
+use crate::error::Error;
+use crate::kdf::{self, Kdf, KEY_LEN};
+use crate::keyslot;
+use crate::metadata::Metadata;
+use crate::recipient::{self, Identity, Recipient};
+use crate::secret::{Key, Zeroizing};
+use crate::stream::{self, StreamReader, StreamWriter};
+use crate::wipe;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::NamedTempFile;
+
+//What to tell the user once a file from the first release has been decrypted (see
+// is_legacy), since nothing showed that its contents are right.
+pub const LEGACY_WARNING: &str = "the file was written by the first release of the app, which did not protect \
+     files against changes, so a modified file or a wrong password may have given wrong contents; check them \
+     and encrypt the file again";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+//EncryptOptions chooses how new files are encrypted. The defaults are what the app itself
+// uses; other programs can change single fields with `..EncryptOptions::default()`.
+// `overwrite` lets encrypt_file replace an existing output file; without it the file is
+// left alone and Error::AlreadyExists is returned. `keyfiles` are combined with the
+// password (see keyfile.rs); with at least one, the password may be empty. The file is
+// also encrypted to the public keys of `recipients` (see recipient.rs), and with some the
+// password and keyfiles may both be left out. Each of these is a key slot of the file
+// (see keyslot.rs), any one of which opens it. With `remove_original`, encrypt_file wipes
+// the input once the output has been verified (see wipe.rs for what that can and cannot
+// do); streams are never removed. `compression` compresses the plaintext first (see
+// compression.rs for when that is a bad idea). Without `store_name` the name of the
+// original file or folder is not recorded at all, not even encrypted; the output of
+// decrypting it is then named after the encrypted file.
+pub struct EncryptOptions {
+    pub cipher: Cipher,
+    pub kdf: Kdf,
+    pub chunk_size: u32,
+    pub overwrite: bool,
+    pub keyfiles: Vec<PathBuf>,
+    pub recipients: Vec<Recipient>,
+    pub remove_original: bool,
+    pub compression: Compression,
+    pub store_name: bool,
+}
+
+impl Default for EncryptOptions {
+    fn default() -> Self {
+        EncryptOptions {
+            cipher: cipher::DEFAULT_CIPHER,
+            kdf: Kdf::default(),
+            chunk_size: stream::DEFAULT_CHUNK_SIZE,
+            overwrite: false,
+            keyfiles: Vec::new(),
+            recipients: Vec::new(),
+            remove_original: false,
+            compression: Compression::None,
+            store_name: true,
+        }
+    }
+}
+
+impl EncryptOptions {
+    // New files are never encrypted with an empty password and no keyfile: the salt and
+    // the KDF still give a unique key, but anyone can derive it. Decrypting with one is
+    // allowed, since the first release did not prevent it. Files encrypted to recipients
+    // need no password at all.
+    pub(crate) fn check_password(&self, password: &str) -> Result<(), Error> {
+        if password.is_empty() && self.keyfiles.is_empty() && self.recipients.is_empty() {
+            return Err(Error::EmptyPassword);
+        }
+        Ok(())
+    }
+
+    // Builds the header for a new file, with a fresh file key, key slots and nonce prefix,
+    // after checking that the options describe something a reader will accept. The
+    // metadata of the original, if there is one, is sealed under the file key, with its
+    // name only if `store_name` is set; the header itself names nothing. Returns the
+    // header together with the key the payload is encrypted with. `file_size` is the size
+    // of the original file, which streams and folders do not have.
+    pub(crate) fn new_header(&self, password: &str, file_size: Option<u64>, metadata: Option<Metadata>) -> Result<(Header, Key), Error> {
+        if !(1..=stream::MAX_CHUNK_SIZE).contains(&self.chunk_size) {
+            return Err(Error::InvalidOptions(format!(
+                "the chunk size must be between 1 and {} bytes",
+                stream::MAX_CHUNK_SIZE
+            )));
+        }
+        self.compression.validate().map_err(|e| Error::InvalidOptions(e.to_string()))?;
+
+        let key = Key::random();
+        let mut slots = Vec::new();
+        if !password.is_empty() || !self.keyfiles.is_empty() {
+            slots.push(keyslot::password_slot(&key, password, &self.keyfiles, self.kdf)?);
+        }
+        for r in &self.recipients {
+            slots.push(KeySlot::Recipient(recipient::wrap(&key, r)?));
+        }
+
+        let header = Header {
+            cipher: self.cipher,
+            slots,
+            nonce: stream::generate_nonce_prefix().to_vec(),
+            chunk_size: self.chunk_size,
+            file_size,
+            archive: false,
+            compression: self.compression,
+            metadata: metadata.map(|m| Metadata { name: m.name.filter(|_| self.store_name), ..m }.seal(key.as_bytes())),
+            // Whole seconds, which is what the header stores.
+            created: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| UNIX_EPOCH + Duration::from_secs(d.as_secs())),
+        };
+        Ok((header, key))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+//DecryptOptions controls which files are accepted for decryption.
+pub struct DecryptOptions {
+    //Whether files written by the first release of the app are decrypted. Those have no
+    // header and were encrypted with AES-256-CBC under a key made from the password
+    // without a KDF. CBC is not authenticated, so a modified file or a wrong password is
+    // not always detected; programs that only read files written by current releases
+    // should turn this off.
+    pub allow_legacy_cbc: bool,
+    //Whether decrypt_file may replace an existing output file.
+    pub overwrite: bool,
+    //The keyfiles the file was encrypted with, in any order.
+    pub keyfiles: Vec<PathBuf>,
+    //The identities tried on a file encrypted to recipients; the password is not used
+    // for those.
+    pub identities: Vec<Identity>,
+}
+
+impl Default for DecryptOptions {
+    fn default() -> Self {
+        DecryptOptions { allow_legacy_cbc: true, overwrite: false, keyfiles: Vec::new(), identities: Vec::new() }
+    }
+}
+
+//This function encrypts the file at `input_path` into `output_path`. The output starts
+// with a container header (see container.rs) holding the cipher, the key slots and the
+// nonce prefix, so the key can be obtained again when the file is decrypted. The nonce
+// prefix is fresh for every encryption, so equal plaintexts never produce equal
+// ciphertexts.
+pub fn encrypt_file(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    options: &EncryptOptions,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<(), Error> {
+    options.check_password(password)?;
+    check_output(input_path, output_path, options.overwrite)?;
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let file_name = input_path.file_name().and_then(|n| n.to_str()).map(String::from);
+    let file_metadata = file.metadata().map_err(|e| Error::with_path(e, input_path))?;
+    let metadata = Metadata::from_file(file_name, &file_metadata);
+    let (header, key) = options.new_header(password, Some(file_metadata.len()), Some(metadata))?;
+
+    // With remove_original, encrypting is the first half of the work and the check the
+    // second.
+    let temp = {
+        let mut encrypting = |read: u64| progress(if options.remove_original { read / 2 } else { read });
+        let mut input = ProgressReader::new(BufReader::new(file), &mut encrypting);
+        write_temp(output_path, |output| encrypt_payload(&mut input, output, &header, key.as_bytes()))?
+    };
+    if options.remove_original {
+        return remove_original(input_path, &file_metadata, temp, output_path, options.overwrite, key.as_bytes(), progress);
+    }
+    persist(temp, output_path, options.overwrite)
+}
+
+//Encrypts everything read from `input` into `output`, in the same format as encrypt_file
+// but without the original file name and metadata, which a stream does not have. Returns the
+// output once the final chunk has been written to it; the output is not flushed.
+pub fn encrypt_stream<R: Read, W: Write>(
+    mut input: R,
+    mut output: W,
+    password: &str,
+    options: &EncryptOptions,
+) -> Result<W, Error> {
+    options.check_password(password)?;
+    let (header, key) = options.new_header(password, None, None)?;
+    encrypt_payload(&mut input, &mut output, &header, key.as_bytes())?;
+    Ok(output)
+}
+
+//This function decrypts a file produced by encrypt_file (or by the first release of the
+// app, see DecryptOptions); encrypted folders are decrypted with archive::decrypt_folder
+// instead. Files are verified chunk by chunk as they are written out, and nothing
+// unverified is ever written; if a chunk fails to authenticate, or the plaintext does not
+// have the size recorded, the partial output is removed. The output gets the modification
+// time and permissions of the original where they were recorded.
+pub fn decrypt_file(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    options: &DecryptOptions,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<(), Error> {
+    check_output(input_path, output_path, options.overwrite)?;
+    decrypt_file_named(input_path, password, options, &mut |_| Ok(output_path.to_path_buf()), progress).map(|_| ())
+}
+
+//Decrypts a file like decrypt_file, to a path chosen once the key has been found, since
+// only then can the original name be read. `output_for` gets that name (None if it was
+// not recorded) and returns the path to write to, or an error that stops the decryption
+// before anything is written. Returns the path written. The name comes from the file
+// being decrypted; OutputNaming::decrypted_path turns it into a path that stays in the
+// output folder.
+pub fn decrypt_file_named(
+    input_path: &Path,
+    password: &str,
+    options: &DecryptOptions,
+    output_for: &mut dyn FnMut(Option<&str>) -> Result<PathBuf, Error>,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<PathBuf, Error> {
+    if options.allow_legacy_cbc && is_legacy(input_path) {
+        return decrypt_legacy(input_path, password, options, output_for, progress);
+    }
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let mut input = ProgressReader::new(BufReader::new(file), progress);
+    let (header, header_bytes) = Header::read_from(&mut input)?;
+    if header.archive {
+        return Err(Error::InvalidOptions(format!(
+            "{} is an encrypted folder; decrypt it with decrypt_folder",
+            input_path.display()
+        )));
+    }
+    let unlocked = unlock(header, header_bytes, input, password, options)?;
+    let output_path = output_for(unlocked.original_name())?;
+    check_output(input_path, &output_path, options.overwrite)?;
+
+    let Unlocked { header, header_bytes, key, metadata, input } = unlocked;
+    write_output(&output_path, options.overwrite, |output| {
+        let size = decrypt_payload(input, output, &header, key.as_bytes(), header_bytes)?;
+        check_size(&header, size).map_err(Error::into_io)?;
+        if let Some(metadata) = metadata {
+            output.flush()?;
+            // Some file systems keep neither; the contents are what matters.
+            let _ = metadata.apply(output.get_ref().as_file());
+        }
+        Ok(())
+    })?;
+    Ok(output_path)
+}
+
+//Decrypts what encrypt_stream (or encrypt_file) wrote, reading it from `input`. The
+// plaintext written to `output` has been verified, but if an error is returned part of
+// it may already have been written; callers that must not keep unverified data should
+// write to a buffer or a temporary file and discard it on error.
+pub fn decrypt_stream<R: Read, W: Write>(
+    mut input: R,
+    mut output: W,
+    password: &str,
+    options: &DecryptOptions,
+) -> Result<W, Error> {
+    let (header, header_bytes) = Header::read_from(&mut input)?;
+    let Unlocked { header, header_bytes, key, input, .. } = unlock(header, header_bytes, input, password, options)?;
+    let size = decrypt_payload(input, &mut output, &header, key.as_bytes(), header_bytes)?;
+    check_size(&header, size)?;
+    Ok(output)
+}
+
+//This function checks that a file decrypts with `password` and has not been modified,
+// without writing the plaintext anywhere. It does the same work as decrypt_file, so it
+// takes about as long. Encrypted folders are checked the same way, without unpacking
+// them. Returns the header of the file, which describes what was checked.
+pub fn verify_file(
+    input_path: &Path,
+    password: &str,
+    options: &DecryptOptions,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<Header, Error> {
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let mut input = ProgressReader::new(BufReader::new(file), progress);
+    let (header, header_bytes) = Header::read_from(&mut input)?;
+    let Unlocked { header, header_bytes, key, input, .. } = unlock(header, header_bytes, input, password, options)?;
+    let size = decrypt_payload(input, &mut io::sink(), &header, key.as_bytes(), header_bytes)?;
+    check_size(&header, size)?;
+    Ok(header)
+}
+
+//Tells whether the file at `path` looks like one written by this app, going by its magic
+// bytes alone. read_header checks the rest of the header.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0u8; 8];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && &magic == container::MAGIC
+}
+
+//Tells whether the file at `path` may have been written by the first release of the
+// app: those have no header, so is_encrypted does not recognise them, and their size is a
+// non-zero multiple of the AES block size. Only decrypting one can tell for sure.
+pub fn is_legacy(path: &Path) -> bool {
+    !is_encrypted(path) && fs::metadata(path).is_ok_and(|m| m.is_file() && m.len() > 0 && m.len() % 16 == 0)
+}
+
+//Reads the container header of an encrypted file, which says how it was encrypted. No
+// password is needed.
+pub fn read_header(input_path: &Path) -> Result<Header, Error> {
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let (header, _) = Header::read_from(&mut BufReader::new(file))?;
+    Ok(header)
+}
+
+//Reads the encrypted metadata of the original file (see metadata.rs), which takes the
+// password, keyfiles or identity the file was encrypted for. Streams have none.
+pub fn read_metadata(input_path: &Path, password: &str, options: &DecryptOptions) -> Result<Option<Metadata>, Error> {
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let (header, _) = Header::read_from(&mut BufReader::new(file))?;
+    if header.metadata.is_none() {
+        return Ok(None);
+    }
+    let key = keyslot::open(&header.slots, password, &options.keyfiles, &options.identities)?;
+    open_metadata(&header, key.as_bytes())
+}
+
+// Writes the header followed by the sealed chunks of everything read from `input`,
+// compressed first if the header says so.
+fn encrypt_payload<R: Read, W: Write>(input: &mut R, output: &mut W, header: &Header, key: &[u8; KEY_LEN]) -> io::Result<()> {
+    header.write_to(output)?;
+    let header_bytes = header.authenticated_bytes()?;
+
+    let writer = StreamWriter::new(output, header.cipher, key, &header.nonce, header.chunk_size, header_bytes);
+    let mut encoder = compression::Encoder::new(writer, header.compression)?;
+    io::copy(input, &mut encoder)?;
+    encoder.finish()?.finish()?;
+    Ok(())
+}
+
+// Decrypts the payload that follows the header in `input` into `output`. `header_bytes`
+// are the header exactly as read, which the payload is authenticated against. Returns the
+// size of the plaintext.
+fn decrypt_payload<R: Read, W: Write>(
+    input: R,
+    output: &mut W,
+    header: &Header,
+    key: &[u8; KEY_LEN],
+    header_bytes: Vec<u8>,
+) -> io::Result<u64> {
+    let mut reader = StreamReader::new(input, header.cipher, key, &header.nonce, header.chunk_size, header_bytes);
+    let size = io::copy(&mut compression::decoder(&mut reader, header.compression)?, output)?;
+    read_to_end(&mut reader)?;
+    Ok(size)
+}
+
+// Decrypts a file written by the first release of the app, which is the whole file
+// encrypted with AES-256-CBC under kdf::password_to_key (see cipher::decrypt_legacy). That
+// release decrypted in memory, and so does this. It named its output "<original>_Encrypt",
+// which gives the original name back.
+fn decrypt_legacy(
+    input_path: &Path,
+    password: &str,
+    options: &DecryptOptions,
+    output_for: &mut dyn FnMut(Option<&str>) -> Result<PathBuf, Error>,
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<PathBuf, Error> {
+    if !options.keyfiles.is_empty() {
+        return Err(Error::KeyfileCount { needed: 0, given: options.keyfiles.len() });
+    }
+    let file = File::open(input_path).map_err(|e| Error::with_path(e, input_path))?;
+    let mut ciphertext = Vec::new();
+    ProgressReader::new(file, progress).read_to_end(&mut ciphertext)?;
+    let key = kdf::password_to_key(password);
+    let plaintext = Zeroizing::new(cipher::decrypt_legacy(key.as_bytes(), &ciphertext)?);
+
+    let name = input_path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix("_Encrypt"));
+    let output_path = output_for(name.filter(|n| !n.is_empty()))?;
+    check_output(input_path, &output_path, options.overwrite)?;
+    write_output(&output_path, options.overwrite, |output| output.write_all(&plaintext))?;
+    Ok(output_path)
+}
+
+// Opens the sealed metadata of the header, if it has any.
+fn open_metadata(header: &Header, key: &[u8; KEY_LEN]) -> Result<Option<Metadata>, Error> {
+    header.metadata.as_deref().map(|sealed| Metadata::open(sealed, key)).transpose()
+}
+
+// An encrypted file whose key has been found, with its metadata opened and `input` at the
+// start of the payload.
+pub(crate) struct Unlocked<R> {
+    pub(crate) header: Header,
+    pub(crate) header_bytes: Vec<u8>,
+    pub(crate) key: Key,
+    pub(crate) metadata: Option<Metadata>,
+    pub(crate) input: R,
+}
+
+impl<R> Unlocked<R> {
+    // The name of the original, from the encrypted metadata.
+    pub(crate) fn original_name(&self) -> Option<&str> {
+        self.metadata.as_ref().and_then(|metadata| metadata.name.as_deref())
+    }
+}
+
+// Finds the key of a file whose header has been read from `input`, and opens its metadata.
+pub(crate) fn unlock<R>(header: Header, header_bytes: Vec<u8>, input: R, password: &str, options: &DecryptOptions) -> Result<Unlocked<R>, Error> {
+    let key = keyslot::open(&header.slots, password, &options.keyfiles, &options.identities)?;
+    let metadata = open_metadata(&header, key.as_bytes())?;
+    Ok(Unlocked { header, header_bytes, key, metadata, input })
+}
+
+// Checks the size of the plaintext against the size the header records. The header is
+// authenticated with the payload, so a mismatch means the file was written wrongly in the
+// first place.
+fn check_size(header: &Header, size: u64) -> Result<(), Error> {
+    match header.file_size {
+        Some(expected) if expected != size => Err(Error::SizeMismatch { expected, found: size }),
+        _ => Ok(()),
+    }
+}
+
+// Reads what is left of a payload. The final chunk, which shows the payload was not cut
+// short, is only verified once the reader reaches it, and a decompressor stops as soon as
+// its data is complete.
+pub(crate) fn read_to_end<R: Read>(reader: &mut StreamReader<R>) -> io::Result<()> {
+    io::copy(reader, &mut io::sink())?;
+    Ok(())
+}
+
+// Decrypts the encrypted file in `temp` with its key and compares the result with the
+// original byte for byte, and only then saves it as `output_path` and wipes the original.
+// If it turns out not to decrypt to the original it is discarded, and whatever was at
+// `output_path` stays as it was. If the check cannot be finished, because of an I/O error
+// or because it was cancelled, nothing is known to be wrong with the new file, so it is
+// saved and the original kept. Either way the original is never lost without a copy that
+// is known to decrypt to it. The original is checked and wiped through a single handle,
+// which must be open on the file that was encrypted, described by `encrypted_from`.
+// Progress goes on from half of the original's size, where encrypting left it.
+fn remove_original(
+    input_path: &Path,
+    encrypted_from: &fs::Metadata,
+    temp: NamedTempFile,
+    output_path: &Path,
+    overwrite: bool,
+    key: &[u8; KEY_LEN],
+    progress: &mut dyn FnMut(u64) -> bool,
+) -> Result<(), Error> {
+    let original = wipe::open(input_path).and_then(|file| {
+        if !wipe::same_file(&file.metadata()?, encrypted_from) {
+            return Err(Error::InvalidOptions(format!("{} was replaced while it was being encrypted", input_path.display())));
+        }
+        Ok(file)
+    });
+    let original = match original {
+        Ok(file) => file,
+        Err(e) => {
+            persist(temp, output_path, overwrite)?;
+            return Err(Error::OriginalKept(Box::new(e)));
+        }
+    };
+    let matches = (|| {
+        let mut file = temp.as_file();
+        file.seek(SeekFrom::Start(0))?;
+        let total = encrypted_from.len();
+        let encrypted_len = file.metadata()?.len().max(1);
+        let mut checking = |read: u64| {
+            let checked = u128::from(read) * u128::from(total - total / 2) / u128::from(encrypted_len);
+            progress(total / 2 + checked as u64)
+        };
+        let mut encrypted = ProgressReader::new(BufReader::new(file), &mut checking);
+        let (header, header_bytes) = Header::read_from(&mut encrypted)?;
+        let mut comparer = Comparer { original: BufReader::new(&original), equal: true, buffer: Zeroizing::default() };
+        match decrypt_payload(encrypted, &mut comparer, &header, key, header_bytes).map_err(Error::from) {
+            Ok(_) => comparer.finish(),
+            Err(Error::Authentication) => Ok(false),
+            Err(e) => Err(e),
+        }
+    })();
+    match matches {
+        Ok(true) => {
+            persist(temp, output_path, overwrite)?;
+            wipe::wipe(original, input_path).map_err(|e| Error::OriginalKept(Box::new(e)))
+        }
+        Ok(false) => Err(Error::VerificationFailed),
+        Err(e) => {
+            persist(temp, output_path, overwrite)?;
+            Err(Error::NotVerified(Box::new(e)))
+        }
+    }
+}
+
+// Checks up front that the output can be written, so the user does not wait for the key
+// derivation only to be told. Replacing the input with its own output would destroy it
+// even when overwriting is allowed.
+pub(crate) fn check_output(input_path: &Path, output_path: &Path, overwrite: bool) -> Result<(), Error> {
+    if let (Ok(input), Ok(output)) = (fs::canonicalize(input_path), fs::canonicalize(output_path)) {
+        if input == output {
+            return Err(Error::OutputIsInput(output_path.to_path_buf()));
+        }
+    }
+    if !overwrite && fs::symlink_metadata(output_path).is_ok() {
+        return Err(Error::AlreadyExists(output_path.to_path_buf()));
+    }
+    Ok(())
+}
+
+// Runs `write` on a temporary file next to the output and, once it has succeeded and the
+// data is on disk, renames the temporary file to the output. Renaming within a folder is
+// atomic, so the output is either the complete new file or whatever was there before.
+// If anything fails the temporary file is deleted when it is dropped.
+pub(crate) fn write_output<F>(output_path: &Path, overwrite: bool, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut BufWriter<NamedTempFile>) -> io::Result<()>,
+{
+    persist(write_temp(output_path, write)?, output_path, overwrite)
+}
+
+// Runs `write` on a temporary file next to the output and returns it once the data is on
+// disk, for persist to give it the output's name.
+fn write_temp<F>(output_path: &Path, write: F) -> Result<NamedTempFile, Error>
+where
+    F: FnOnce(&mut BufWriter<NamedTempFile>) -> io::Result<()>,
+{
+    let temp = temp_file_beside(output_path, |builder, dir| builder.tempfile_in(dir))?;
+    let mut output = BufWriter::new(temp);
+    write(&mut output)?;
+    let temp = output.into_inner().map_err(|e| e.into_error())?;
+    temp.as_file().sync_all()?;
+    Ok(temp)
+}
+
+// Renames a temporary file from write_temp to the output.
+fn persist(temp: NamedTempFile, output_path: &Path, overwrite: bool) -> Result<(), Error> {
+    // persist_noclobber fails rather than replace a file that appeared in the meantime.
+    let persisted = if overwrite { temp.persist(output_path) } else { temp.persist_noclobber(output_path) };
+    persisted.map_err(|e| match e.error.kind() {
+        io::ErrorKind::AlreadyExists => Error::AlreadyExists(output_path.to_path_buf()),
+        _ => Error::with_path(e.error, output_path),
+    })?;
+    sync_dir(parent_dir(output_path));
+    Ok(())
+}
+
+// Returns the folder `path` is in, which is the current one for a bare name.
+pub(crate) fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+// Creates a temporary file or folder next to `path` with `create`, which is given a
+// so it is hidden, and a random part and ".partial" at the end, so it is neither taken
+// so it is hidden, and ".partial" and a random part at the end, so it is neither taken
+// for the finished output nor clashes with another one.
+pub(crate) fn temp_file_beside<T>(path: &Path, create: impl FnOnce(&tempfile::Builder, &Path) -> io::Result<T>) -> Result<T, Error> {
+    let dir = parent_dir(path);
+    let mut prefix = OsString::from(".");
+    prefix.push(path.file_name().unwrap_or_default());
+    prefix.push(".");
+    create(tempfile::Builder::new().prefix(&prefix).suffix(".partial"), dir).map_err(|e| Error::with_path(e, dir))
+}
+
+// Makes the rename itself durable. This is only possible (and only needed) on Unix, and
+// a failure here does not undo the write, so it is not reported.
+#[cfg(unix)]
+pub(crate) fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_dir: &Path) {}
+
+// Compares everything written to it with what is read from the original, one write at a
+// time. The copy of the original is wiped like any other plaintext.
+struct Comparer<R: Read> {
+    original: R,
+    equal: bool,
+    buffer: Zeroizing<Vec<u8>>,
+}
+
+impl<R: Read> Comparer<R> {
+    // Whether everything written matched, and the original has nothing more.
+    fn finish(mut self) -> Result<bool, Error> {
+        let mut rest = [0u8; 1];
+        Ok(self.equal && self.original.read(&mut rest)? == 0)
+    }
+}
+
+impl<R: Read> Write for Comparer<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.equal {
+            self.buffer.resize(buf.len(), 0);
+            match self.original.read_exact(&mut self.buffer) {
+                Ok(()) => self.equal = self.buffer.as_slice() == buf,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => self.equal = false,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Counts the bytes read through it and hands the running total to the progress callback.
+// When the callback returns false, the next read fails with Error::Cancelled.
+pub(crate) struct ProgressReader<'a, R: Read> {
+    inner: R,
+    processed: u64,
+    progress: &'a mut dyn FnMut(u64) -> bool,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, progress: &'a mut dyn FnMut(u64) -> bool) -> Self {
+        ProgressReader { inner, processed: 0, progress }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !(self.progress)(self.processed) {
+            return Err(Error::Cancelled.into_io());
+        }
+        let n = self.inner.read(buf)?;
+        self.processed += n as u64;
+        Ok(n)
+    }
+}
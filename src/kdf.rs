@@ -0,0 +1,179 @@
+/*
+Password-based key derivation. A password is never used as an AES key directly:
+it is stretched with a slow, salted KDF so that guessing attacks have to pay the
+full cost of the function for every candidate password. Argon2id is the default
+because it is memory-hard; PBKDF2-HMAC-SHA256 is kept as a lighter alternative
+for machines that cannot spare the memory. The chosen function and its cost
+parameters are written next to the ciphertext so decryption can re-derive the key.
+ */
+use crate::secret::Key;
+use argon2::{Algorithm, Argon2, Params, Version};
+use hmac::Hmac;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+//Length in bytes of every derived key (AES-256).
+pub const KEY_LEN: usize = 32;
+
+//Length in bytes of the random salt generated for each new file.
+pub const SALT_LEN: usize = 16;
+
+// Identifiers written in front of the parameters so the reader knows how to parse them.
+const PBKDF2_SHA256_ID: u8 = 1;
+const ARGON2ID_ID: u8 = 2;
+
+// Upper bounds enforced when reading parameters back from a file, so a crafted file
+// cannot make the app allocate tens of gigabytes or spin for hours before failing.
+const MAX_ARGON2_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+const MAX_ITERATIONS: u32 = 100_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//Kdf is the set of supported key derivation functions, each variant carrying the cost
+// parameters it was (or will be) run with. Adding a new function means adding a variant,
+// an identifier byte and the matching arms in derive_key, write_to and read_from.
+pub enum Kdf {
+    Pbkdf2Sha256 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+//Argon2id with 64 MiB of memory and 3 passes is the default for new files. This follows
+// the second recommended option of RFC 9106 and takes well under a second on a desktop.
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Argon2id {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+impl fmt::Display for Kdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                write!(f, "PBKDF2-HMAC-SHA256 ({} iterations)", iterations)
+            }
+            Kdf::Argon2id { memory_kib, iterations, parallelism } => write!(
+                f,
+                "Argon2id ({} KiB memory, {} iterations, {} lanes)",
+                memory_kib, iterations, parallelism
+            ),
+        }
+    }
+}
+
+impl Kdf {
+    //Stretches the password into a 32 byte key using the salt stored with the file.
+    // The parameters are checked by read_from (or come from our own defaults), so the
+    // only way Argon2 can refuse them here is a programming error.
+    pub fn derive_key(&self, password: &[u8], salt: &[u8]) -> Key {
+        let mut key = Key::empty();
+        match *self {
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, iterations, key.as_mut_bytes());
+            }
+            Kdf::Argon2id { memory_kib, iterations, parallelism } => {
+                let params = Params::new(memory_kib, iterations, parallelism, Some(KEY_LEN))
+                    .expect("Argon2 parameters were validated");
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(password, salt, key.as_mut_bytes())
+                    .expect("Argon2 parameters were validated");
+            }
+        }
+        key
+    }
+
+    //Serializes the KDF identifier followed by its parameters as little-endian u32 values.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match *self {
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                out.write_all(&[PBKDF2_SHA256_ID])?;
+                out.write_all(&iterations.to_le_bytes())
+            }
+            Kdf::Argon2id { memory_kib, iterations, parallelism } => {
+                out.write_all(&[ARGON2ID_ID])?;
+                out.write_all(&memory_kib.to_le_bytes())?;
+                out.write_all(&iterations.to_le_bytes())?;
+                out.write_all(&parallelism.to_le_bytes())
+            }
+        }
+    }
+
+    //Parses what write_to produced. Unknown identifiers and parameters outside the range
+    // this app is willing to run are reported as InvalidData instead of being trusted.
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Kdf> {
+        let mut id = [0u8; 1];
+        input.read_exact(&mut id)?;
+        let kdf = match id[0] {
+            PBKDF2_SHA256_ID => Kdf::Pbkdf2Sha256 { iterations: read_u32(input)? },
+            ARGON2ID_ID => Kdf::Argon2id {
+                memory_kib: read_u32(input)?,
+                iterations: read_u32(input)?,
+                parallelism: read_u32(input)?,
+            },
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown key derivation function id {}", other),
+                ))
+            }
+        };
+        kdf.validate()?;
+        Ok(kdf)
+    }
+
+    //Checks that the parameters are within the range this app is willing to run, which
+    // read_from enforces on every file.
+    pub fn validate(&self) -> io::Result<()> {
+        let valid = match *self {
+            Kdf::Pbkdf2Sha256 { iterations } => (1..=MAX_ITERATIONS).contains(&iterations),
+            Kdf::Argon2id { memory_kib, iterations, parallelism } => {
+                memory_kib <= MAX_ARGON2_MEMORY_KIB
+                    && iterations <= MAX_ITERATIONS
+                    && Params::new(memory_kib, iterations, parallelism, Some(KEY_LEN)).is_ok()
+            }
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported key derivation parameters: {}", self),
+            ))
+        }
+    }
+}
+
+//Returns the key the first release of the app used: the password bytes XORed into 32
+// zero bytes, with no salt and no stretching. It is only here so files from that release
+// can still be decrypted (see files.rs), and must never be used for new files.
+pub fn password_to_key(password: &str) -> Key {
+    let mut key = Key::empty();
+    for (i, byte) in password.as_bytes().iter().enumerate() {
+        key.as_mut_bytes()[i % KEY_LEN] ^= *byte;
+    }
+    key
+}
+
+//Fills a fresh salt from the operating system's CSPRNG.
+pub fn generate_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
@@ -0,0 +1,92 @@
+/*
+Keyfiles: files whose contents take part in deriving the key, in addition to the password
+or instead of it. Any file can serve as a keyfile, but generate writes one of random bytes,
+which is the safe choice. Every keyfile is hashed with SHA-256 and the hashes are combined
+into one digest, sorted first so the order the keyfiles are given in does not matter. The
+digest is appended to the password before it goes into the KDF, so without the keyfiles a
+guessed password is useless, and a file encrypted with keyfiles and no password is only as
+safe as the keyfiles are kept.
+ */
+use crate::error::Error;
+use crate::secret::Zeroizing;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+//Length in bytes of the keyfiles written by generate (256 bits, the size of the key).
+pub const KEYFILE_LEN: usize = 32;
+
+//Writes a new keyfile of random bytes to `path`. An existing file is never replaced,
+// since it may be the keyfile that other files were encrypted with.
+pub fn generate(path: &Path) -> Result<(), Error> {
+    let mut bytes = Zeroizing::new([0u8; KEYFILE_LEN]);
+    OsRng.fill_bytes(bytes.as_mut());
+
+    let mut file = create_private(path)?;
+    file.write_all(bytes.as_ref())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+//Creates a file for a secret, such as a keyfile or an identity, failing if `path`
+// already exists. On Unix the file is only readable by its owner.
+pub(crate) fn create_private(path: &Path) -> Result<File, Error> {
+    create_new(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => Error::AlreadyExists(path.to_path_buf()),
+        _ => Error::with_path(e, path),
+    })
+}
+
+#[cfg(unix)]
+fn create_new(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+//Returns what the key is derived from: the password on its own when there are no
+// keyfiles (as in files written before keyfiles existed), otherwise the password followed
+// by the combined digest of the keyfiles. The capacity is reserved up front, so the
+// password is never left behind in a smaller buffer that was grown.
+pub(crate) fn kdf_input(password: &str, keyfiles: &[PathBuf]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let mut input = Zeroizing::new(Vec::with_capacity(password.len() + 32));
+    input.extend_from_slice(password.as_bytes());
+    if !keyfiles.is_empty() {
+        input.extend_from_slice(digest(keyfiles)?.as_ref());
+    }
+    Ok(input)
+}
+
+fn digest(keyfiles: &[PathBuf]) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let mut hashes = Zeroizing::new(Vec::with_capacity(keyfiles.len()));
+    for path in keyfiles {
+        hashes.push(hash_file(path)?);
+    }
+    hashes.sort_unstable();
+
+    let mut combined = Sha256::new();
+    for hash in hashes.iter() {
+        combined.update(hash);
+    }
+    Ok(Zeroizing::new(combined.finalize().into()))
+}
+
+// An empty keyfile adds nothing an attacker would have to guess, and is more likely a
+// wrong file than a deliberate choice, so it is refused.
+fn hash_file(path: &Path) -> Result<[u8; 32], Error> {
+    let mut file = File::open(path).map_err(|e| Error::with_path(e, path))?;
+    let mut hasher = Sha256::new();
+    let length = io::copy(&mut file, &mut hasher)?;
+    if length == 0 {
+        return Err(Error::InvalidOptions(format!("the keyfile {} is empty", path.display())));
+    }
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,193 @@
+/*
+Key slots, in the manner of LUKS. The payload of a file is encrypted with a random file
+key, and the header stores that key several times, once per slot, each time wrapped under
+a different key: one derived from a password (and keyfiles), or one agreed with a
+recipient's public key (see recipient.rs). Any one slot opens the file. A password slot
+seals the file key with ChaCha20-Poly1305 under the key the KDF derives from its own salt.
+
+The slots are not part of what the payload is authenticated against (see container.rs),
+so add_slot and remove_slot change them by rewriting the header alone, without
+re-encrypting the payload. Removing a slot only affects the file it is removed from:
+copies and backups made earlier still open with it, and whoever could open the file
+before may have kept its file key.
+ */
+use crate::cipher::{AeadCipher, AuthenticationError};
+use crate::container::{Cipher, FormatError, Header, KeySlot};
+use crate::error::Error;
+use crate::files::{self, DecryptOptions};
+use crate::kdf::{self, Kdf, KEY_LEN};
+use crate::keyfile;
+use crate::recipient::{self, Identity, Recipient};
+use crate::secret::{Key, Zeroizing};
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// Every key a WrapCipher is made with seals exactly one thing, so a fixed nonce is safe.
+const WRAP_NONCE: [u8; 12] = [0; 12];
+
+//WrapCipher seals a single secret with ChaCha20-Poly1305 under a key used for nothing
+// else: a file key in a password slot or a recipient stanza (see recipient.rs), or the
+// metadata of a file (see metadata.rs).
+pub(crate) struct WrapCipher(AeadCipher);
+
+impl WrapCipher {
+    pub(crate) fn seal(&self, secret: &[u8]) -> Vec<u8> {
+        self.0.seal(&WRAP_NONCE, &[], secret)
+    }
+
+    pub(crate) fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, AuthenticationError> {
+        self.0.open(&WRAP_NONCE, &[], sealed)
+    }
+
+    //Opens a file key sealed with seal, or returns None if the key is not the one it was
+    // sealed under.
+    pub(crate) fn open_key(&self, wrapped: &[u8]) -> Option<Key> {
+        let bytes = Zeroizing::new(self.open(wrapped).ok()?);
+        if bytes.len() != KEY_LEN {
+            return None;
+        }
+        let mut key = Key::empty();
+        key.as_mut_bytes().copy_from_slice(&bytes);
+        Some(key)
+    }
+}
+
+//Returns the WrapCipher that seals under `key`.
+pub(crate) fn wrap_cipher(key: &Key) -> WrapCipher {
+    WrapCipher(AeadCipher::new(Cipher::ChaCha20Poly1305, key.as_bytes()))
+}
+
+//NewSlot is a way of opening a file that add_slot adds to it.
+pub enum NewSlot<'a> {
+    //A password combined with keyfiles, either of which may be missing but not both.
+    Password { password: &'a str, keyfiles: &'a [PathBuf], kdf: Kdf },
+    Recipient(Recipient),
+}
+
+//Adds a slot to the encrypted file at `path`, which is opened with `password` or the
+// keyfiles and identities in `options` first. Returns the file's new header.
+pub fn add_slot(path: &Path, password: &str, options: &DecryptOptions, slot: &NewSlot) -> Result<Header, Error> {
+    rewrite(path, password, options, |slots, file_key| {
+        let slot = match slot {
+            NewSlot::Password { password, keyfiles, kdf } => password_slot(file_key, password, keyfiles, *kdf)?,
+            NewSlot::Recipient(r) => KeySlot::Recipient(recipient::wrap(file_key, r)?),
+        };
+        slots.push(slot);
+        Ok(())
+    })
+}
+
+//Removes slot `index` (counted from 0, in the order the header lists them) from the
+// encrypted file at `path`, after opening it with `password` or the keyfiles and identities
+// in `options`; the slot that opened it may be the one removed. The last slot is never
+// removed, since the file could not be opened at all without it. Returns the new header.
+pub fn remove_slot(path: &Path, password: &str, options: &DecryptOptions, index: usize) -> Result<Header, Error> {
+    rewrite(path, password, options, |slots, _| {
+        if index >= slots.len() {
+            return Err(Error::InvalidOptions(format!("the file has no key slot {}", index + 1)));
+        }
+        if slots.len() == 1 {
+            return Err(Error::InvalidOptions(String::from("the last key slot cannot be removed")));
+        }
+        slots.remove(index);
+        Ok(())
+    })
+}
+
+//Wraps `file_key` under a key derived from the password and keyfiles with `kdf` and a
+// fresh salt.
+pub fn password_slot(file_key: &Key, password: &str, keyfiles: &[PathBuf], kdf: Kdf) -> Result<KeySlot, Error> {
+    if password.is_empty() && keyfiles.is_empty() {
+        return Err(Error::EmptyPassword);
+    }
+    kdf.validate().map_err(|e| Error::InvalidOptions(e.to_string()))?;
+    let count = u8::try_from(keyfiles.len())
+        .map_err(|_| Error::InvalidOptions(format!("at most {} keyfiles can be used", u8::MAX)))?;
+    let salt = kdf::generate_salt().to_vec();
+    let wrap_key = kdf.derive_key(&keyfile::kdf_input(password, keyfiles)?, &salt);
+    let wrapped = wrap_cipher(&wrap_key).seal(file_key.as_bytes());
+    Ok(KeySlot::Password { kdf, salt, keyfiles: count, wrapped })
+}
+
+//Opens the first slot the identities or the password and keyfiles fit. Public keys are
+// tried first, since that costs next to nothing, and only the password slots made with
+// as many keyfiles as were given, since every one tried costs a run of the KDF. When none
+// opens, the error says what is most likely wrong.
+pub(crate) fn open(slots: &[KeySlot], password: &str, keyfiles: &[PathBuf], identities: &[Identity]) -> Result<Key, Error> {
+    for slot in slots {
+        if let KeySlot::Recipient(stanza) = slot {
+            if let Some(key) = recipient::unwrap(stanza, identities) {
+                return Ok(key);
+            }
+        }
+    }
+
+    let mut input: Option<Zeroizing<Vec<u8>>> = None;
+    let mut tried = false;
+    let mut needed = None;
+    for slot in slots {
+        let KeySlot::Password { kdf, salt, keyfiles: count, wrapped } = slot else {
+            continue;
+        };
+        if usize::from(*count) != keyfiles.len() {
+            needed.get_or_insert(usize::from(*count));
+            continue;
+        }
+        tried = true;
+        // No slot is ever made without both, so there is no need to run the KDF.
+        if password.is_empty() && keyfiles.is_empty() {
+            continue;
+        }
+        let input = match &mut input {
+            Some(input) => input,
+            None => input.insert(keyfile::kdf_input(password, keyfiles)?),
+        };
+        if let Some(key) = wrap_cipher(&kdf.derive_key(input, salt)).open_key(wrapped) {
+            return Ok(key);
+        }
+    }
+
+    match needed {
+        _ if tried => Err(Error::Authentication),
+        Some(needed) => Err(Error::KeyfileCount { needed, given: keyfiles.len() }),
+        None => Err(Error::NoMatchingIdentity),
+    }
+}
+
+// Opens the file, lets `change` edit its slots, and writes the file again with the new
+// header in front of the payload as it was. The file is replaced the same way outputs are
+// written (see files.rs), so an interruption leaves the old file in place, and the new
+// file gets the permissions and modification time of the old one.
+fn rewrite<F>(path: &Path, password: &str, options: &DecryptOptions, change: F) -> Result<Header, Error>
+where
+    F: FnOnce(&mut Vec<KeySlot>, &Key) -> Result<(), Error>,
+{
+    let file = File::open(path).map_err(|e| Error::with_path(e, path))?;
+    let metadata = file.metadata()?;
+    let mut input = BufReader::new(file);
+    let (mut header, header_bytes) = Header::read_from(&mut input)?;
+    let slots = &mut header.slots;
+    let file_key = open(slots, password, &options.keyfiles, &options.identities)?;
+    change(slots, &file_key)?;
+
+    // The payload stays valid only if everything it is authenticated against is written
+    // back exactly as it was read.
+    if header.authenticated_bytes()? != header_bytes {
+        return Err(Error::from(FormatError::Invalid(String::from(
+            "the header cannot be written back unchanged",
+        ))));
+    }
+    files::write_output(path, true, |output| {
+        header.write_to(output)?;
+        io::copy(&mut input, output)?;
+        output.flush()?;
+        let file = output.get_ref().as_file();
+        file.set_permissions(metadata.permissions())?;
+        if let Ok(modified) = metadata.modified() {
+            file.set_modified(modified)?;
+        }
+        Ok(())
+    })?;
+    Ok(header)
+}
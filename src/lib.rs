@@ -0,0 +1,53 @@
+/*
+The encryption core shared by the GUI (main.rs) and the command-line tool (bin/cli.rs).
+Files are encrypted with AES-256 in GCM mode (see cipher.rs), which also detects
+modified files and wrong passwords, with the key derived from the password by a salted,
+memory-hard KDF (see kdf.rs). Encrypted files are wrapped in a versioned container (see
+container.rs) that records how they were encrypted, and are processed in fixed-size
+chunks (see stream.rs), so even files larger than the available memory can be
+encrypted. files.rs ties these together into whole-file operations, naming.rs decides
+what the output files are called, and error.rs holds the errors they report.
+strength.rs estimates how good a new password is, and keyfile.rs lets files stand in for
+the password or add to it; recipient.rs encrypts files to other people's public keys
+as well. keyslot.rs stores the key of a file once for each password or public key that
+opens it, and adds and removes them later. secret.rs wipes keys and decrypted data from
+memory once they are no longer needed, and wipe.rs overwrites and deletes the originals
+of encrypted files. archive.rs encrypts whole folders as a single file,
+compression.rs compresses the plaintext before it is encrypted, and metadata.rs keeps
+the original's name, time and permissions where only those who can decrypt it see
+them.
+
+The functions other programs need are re-exported here:
+
+    use aes256_encryption_gui_app::{decrypt_stream, encrypt_stream, DecryptOptions, EncryptOptions};
+
+    let encrypted = encrypt_stream(&b"secret"[..], Vec::new(), "password", &EncryptOptions::default())?;
+    let decrypted = decrypt_stream(&encrypted[..], Vec::new(), "password", &DecryptOptions::default())?;
+
+Files written this way can be opened by the app and the command-line tool, and the
+other way round.
+ */
+pub mod archive;
+pub mod cipher;
+pub mod compression;
+pub mod container;
+pub mod error;
+pub mod files;
+pub mod kdf;
+pub mod keyfile;
+pub mod keyslot;
+pub mod metadata;
+pub mod naming;
+pub mod recipient;
+pub mod secret;
+pub mod stream;
+pub mod strength;
+pub mod wipe;
+
+pub use compression::Compression;
+pub use container::Cipher;
+pub use error::Error;
+pub use files::{decrypt_file, decrypt_file_named, decrypt_stream, encrypt_file, encrypt_stream, verify_file, DecryptOptions, EncryptOptions};
+pub use kdf::Kdf;
+pub use metadata::Metadata;
+pub use naming::OutputNaming;
@@ -1,55 +1,188 @@
 /*
 The code is a simple file encryption/decryption application using the Iced GUI library.
 It allows the user to browse for a file, input a password, choose between encryption
-and decryption, and process the file accordingly. The encryption/decryption is done
-using the AES-256 algorithm in CBC mode with PKCS7 padding.
+and decryption, and process the file accordingly. The encryption itself lives in the
+library (see lib.rs), which the command-line tool (see bin/cli.rs) uses as well, so
+files written by one can be read by the other. The work runs on a background thread
+(see worker.rs) while the window shows its progress. Several files, or whole folders,
+can be queued and processed in one go (see batch.rs).
  */
-use aes::{Aes256, NewBlockCipher};
-use block_modes::{BlockMode, Cbc};
-use block_modes::block_padding::Pkcs7;
-use generic_array::GenericArray;
-use iced::{button, text_input, Application, Button, Column, Command, Container, Element, Length, Radio, Row, Settings, Text, TextInput};
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use std::str;
+mod batch;
+mod worker;
+
+use aes256_encryption_gui_app::compression::{DEFAULT_DEFLATE_LEVEL, DEFAULT_ZSTD_LEVEL};
+use aes256_encryption_gui_app::container::{Header, KeySlot, CURRENT_VERSION};
+use aes256_encryption_gui_app::keyslot::{self, NewSlot};
+use aes256_encryption_gui_app::metadata::{self, Metadata};
+use aes256_encryption_gui_app::naming::{self, OutputNaming};
+use aes256_encryption_gui_app::recipient::{self, Identity, Recipient};
+use aes256_encryption_gui_app::secret::Zeroizing;
+use aes256_encryption_gui_app::strength::{self, Strength};
+use aes256_encryption_gui_app::{files, keyfile, Compression, DecryptOptions, Error, Kdf};
+use batch::ItemStatus;
+use iced::{button, container, pick_list, scrollable, text_input, Application, Background, Button, Checkbox, Color, Column, Command, Container, Element, Length, PickList, ProgressBar, Radio, Row, Scrollable, Settings, Subscription, Text, TextInput};
+use iced_native::{subscription, window, Event};
 use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
-//a struct App contains different elements such as file_path, password,
+//a struct App contains different elements such as the queue of files, password,
 // mode, browse_button, password_input, process_button and message. The App's
 // update and view methods are overridden to update the App with a Message
-// enum or produce an Element. The password_to_key function is used to generate
-// an AES-256 key from the provided password. The main function runs the application.
+// enum or produce an Element. The AES-256 key is derived from the provided password
+// by the kdf module. The password is wiped from memory when it is replaced, and with
+// forget_password also once a batch is over.
+// When a single encrypted file is selected its header is kept, so its details and key
+// slots can be shown and its key slots changed; `metadata` is what its encrypted
+// metadata says, once read.
+// With archive_folders, folders are queued whole and encrypted into a single file. New
+// files are compressed as `compression` says, and with hide_names their names are not
+// stored at all. The main function runs the application.
 struct App {
-    file_path: Option<PathBuf>,
-    password: String,
+    queue: batch::Queue,
+    password: Zeroizing<String>,
+    confirm_password: Zeroizing<String>,
+    forget_password: bool,
+    keyfiles: Vec<PathBuf>,
+    recipients: Vec<Recipient>,
+    recipient_input: String,
+    identities: Vec<Identity>,
+    header: Option<Header>,
+    metadata: Option<Metadata>,
+    show_metadata_button: button::State,
+    new_password: Zeroizing<String>,
+    new_confirm_password: Zeroizing<String>,
+    new_keyfiles: Vec<PathBuf>,
+    updating_slots: bool,
     mode: Mode,
+    compression: Compression,
+    compression_list: pick_list::State<Compression>,
     browse_button: button::State,
+    browse_folder_button: button::State,
+    archive_folders: bool,
+    clear_button: button::State,
+    queue_scroll: scrollable::State,
+    files_hovered: bool,
+    extension: String,
+    extension_input: text_input::State,
+    output_dir: Option<PathBuf>,
+    output_dir_button: button::State,
+    same_dir_button: button::State,
+    save_as_button: button::State,
+    if_exists: IfExists,
+    conflict: Option<Conflict>,
+    remove_originals: bool,
+    hide_names: bool,
+    overwrite_button: button::State,
+    rename_button: button::State,
+    skip_button: button::State,
     password_input: text_input::State,
+    confirm_input: text_input::State,
+    add_keyfiles_button: button::State,
+    clear_keyfiles_button: button::State,
+    generate_keyfile_button: button::State,
+    recipient_input_state: text_input::State,
+    add_recipient_button: button::State,
+    load_recipients_button: button::State,
+    clear_recipients_button: button::State,
+    add_identities_button: button::State,
+    clear_identities_button: button::State,
+    generate_identity_button: button::State,
+    slot_buttons: Vec<button::State>,
+    new_password_input: text_input::State,
+    new_confirm_input: text_input::State,
+    add_new_keyfiles_button: button::State,
+    clear_new_keyfiles_button: button::State,
+    add_password_slot_button: button::State,
+    add_recipient_slot_button: button::State,
     process_button: button::State,
-    message: String, // Added this field to store the message text
+    cancel_button: button::State,
+    message: Status, // Added this field to store the message text
+    job: Option<worker::Job>,
+    next_job_id: u64,
+    progress: Progress,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-//Mode is an enumeration type which defines a set of named constants. In this case,
-// the two constants are "Encrypt" and "Decrypt", representing the two possible modes
-// of operation. This type of data structure allows for cleaner, more reliable code by
-// making the intention of the code clearer and less prone to errors.
-enum Mode {
-    Encrypt,
-    Decrypt,
+#[derive(Debug, Clone)]
+//Status is the message shown at the bottom of the window. Errors are kept apart from
+// ordinary information so the view can make them stand out.
+enum Status {
+    Info(String),
+    Error(String),
 }
 
-//The above code is creating a default implementation of the Mode enum. The default
-// implementation is set to Mode::Encrypt, meaning that if no other Mode is specified,
-// the code will use the encryption mode.
-impl Default for Mode {
+impl Default for Status {
     fn default() -> Self {
-        Mode::Encrypt
+        Status::Info(String::new())
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+//IfExists is what happens when a file's output already exists: the user is asked each
+// time, or the same answer is given for every file of the batch.
+enum IfExists {
+    #[default]
+    Ask,
+    Overwrite,
+    Rename,
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+//Conflict is a file waiting for the user to say what to do about its existing output.
+struct Conflict {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy)]
+//Progress tracks how far the running job has got. The start time is kept so the view
+// can estimate how long the rest of the file will take.
+struct Progress {
+    processed: u64,
+    total: u64,
+    started: Instant,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress { processed: 0, total: 0, started: Instant::now() }
+    }
+}
+
+impl Progress {
+    //Estimates the remaining time from the average speed so far. There is no estimate
+    // until a little data has gone through, since the first reads include the time spent
+    // deriving the key.
+    fn remaining(&self) -> Option<Duration> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if self.processed == 0 || self.total == 0 || elapsed < 0.5 {
+            return None;
+        }
+        let rate = self.processed as f64 / elapsed;
+        Some(Duration::from_secs_f64(self.total.saturating_sub(self.processed) as f64 / rate))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+//Mode is an enumeration type which defines a set of named constants. In this case,
+// the three constants are "Encrypt", "Decrypt" and "Verify", representing the possible
+// modes of operation; Verify checks that files decrypt without writing anything. This
+// type of data structure allows for cleaner, more reliable code by making the intention
+// of the code clearer and less prone to errors.
+//The default is set to Mode::Encrypt, meaning that if no other Mode is specified,
+// the code will use the encryption mode.
+enum Mode {
+    #[default]
+    Encrypt,
+    Decrypt,
+    Verify,
+}
+
 //The function fmt is overriding the implementation of std::fmt::Display for the Mode enum.
 // The implementation uses a match statement to check for the variant of the enum, and then
 // use the write! macro to print the string representation.
@@ -59,6 +192,27 @@ impl fmt::Display for Mode {
         match self {
             Mode::Encrypt => write!(f, "Encrypt"),
             Mode::Decrypt => write!(f, "Decrypt"),
+            Mode::Verify => write!(f, "Verify"),
+        }
+    }
+}
+
+impl Mode {
+    //What the mode did to a file, as in "Encrypted 3 of 4 files".
+    fn past_tense(&self) -> &'static str {
+        match self {
+            Mode::Encrypt => "Encrypted",
+            Mode::Decrypt => "Decrypted",
+            Mode::Verify => "Verified",
+        }
+    }
+
+    //What the mode does to a file, as in "Cannot decrypt notes.txt.aes".
+    fn verb(&self) -> &'static str {
+        match self {
+            Mode::Encrypt => "encrypt",
+            Mode::Decrypt => "decrypt",
+            Mode::Verify => "verify",
         }
     }
 }
@@ -67,18 +221,70 @@ impl fmt::Display for Mode {
 //The Message enum is being used to define a set of types that could be used for
 // communication between different parts of an application. The different variants of
 // the Message enum define specific types of messages that can be sent and received.
-// For example, the BrowseFile variant would be sent to request the user to browse for
-// a file, and the FileSelected variant would be sent in response with the path of the
-// selected file. Similarly, the PasswordChanged variant would be sent to indicate the
-// user has changed their password, and the ModeChanged variant would be sent to indicate
-// the user has changed their mode. Finally, the ProcessFile variant would be sent to
-// indicate that the application should process the provided file.
+// For example, the BrowseFiles variant would be sent to request the user to browse for
+// files, and the FilesSelected variant would be sent in response with the paths of the
+// selected files, which are added to the queue. The variants are grouped below by the
+// part of the window that sends them.
 enum Message {
-    BrowseFile,
-    FileSelected(PathBuf),
+    // Adding files to the queue: BrowseFolder adds a folder, whole or as its files as
+    // ArchiveFoldersToggled decides, and files dragged onto the window arrive one by one
+    // as FileDropped, after FilesHovered.
+    BrowseFiles,
+    BrowseFolder,
+    ArchiveFoldersToggled(bool),
+    FilesSelected(Vec<PathBuf>),
+    FilesHovered(bool),
+    FileDropped(PathBuf),
+    ClearFiles,
+    // The password, its confirmation, the keyfiles used with it, and whether it is
+    // cleared once the files have been processed.
     PasswordChanged(String),
+    ConfirmChanged(String),
+    ForgetPasswordToggled(bool),
+    AddKeyfiles,
+    ClearKeyfiles,
+    GenerateKeyfile,
+    // The public keys files are encrypted to instead of a password, the secret keys they
+    // are decrypted with, and GenerateIdentity to make a new key pair.
+    RecipientInputChanged(String),
+    AddRecipient,
+    LoadRecipients,
+    ClearRecipients,
+    AddIdentities,
+    ClearIdentities,
+    GenerateIdentity,
+    // A new key slot for the selected file, which AddPasswordSlot or AddRecipientSlot
+    // adds to it and RemoveSlot takes away; SlotsUpdated reports back.
+    NewPasswordChanged(String),
+    NewConfirmChanged(String),
+    AddNewKeyfiles,
+    ClearNewKeyfiles,
+    AddPasswordSlot,
+    AddRecipientSlot,
+    RemoveSlot(usize),
+    SlotsUpdated(Result<Header, Error>),
+    // Reading the encrypted metadata of the selected file.
+    ShowMetadata,
+    MetadataRead(PathBuf, Result<Option<Metadata>, Error>),
+    // What is done to the files, and how new ones are compressed.
     ModeChanged(Mode),
-    ProcessFile,
+    CompressionChanged(Compression),
+    // Where outputs are written, what happens when one already exists (ResolveConflict
+    // answers for a single file), whether encrypted files replace their originals, and
+    // whether they record their names.
+    ExtensionChanged(String),
+    ChooseOutputDir,
+    ResetOutputDir,
+    SaveAs,
+    IfExistsChanged(IfExists),
+    ResolveConflict(IfExists),
+    RemoveOriginalsToggled(bool),
+    HideNamesToggled(bool),
+    // Processing the queued files; while a file is being processed the worker reports
+    // back through Worker, and CancelProcessing asks it to stop.
+    ProcessFiles,
+    CancelProcessing,
+    Worker(worker::Event),
 }
 
 //The Application trait is a trait from the iced library that is used to define
@@ -110,58 +316,363 @@ impl Application for App {
 
     //The function is matching against a given message and depending on what message is provided,
     // it will do different things. In this case, it is checking if the message is a
-    // Message::BrowseFile and if it is, it will open up a file dialog and set the
-    // file_path to the selected file. It then returns a Command that will perform
-    // the Message::FileSelected action, with the selected file as the parameter.
+    // Message::BrowseFiles and if it is, it will open up a file dialog for the user to
+    // select one or more files. It then returns a Command that will perform the
+    // Message::FilesSelected action, with the selected files as the parameter.
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
-            Message::BrowseFile => {
-                let file_dialog = rfd::FileDialog::new().pick_file();
-                if let Some(file) = file_dialog {
-                    self.file_path = Some(file.clone());
-                    return Command::perform(async move { Message::FileSelected(file) }, |msg| msg);
+            Message::BrowseFiles => {
+                if let Some(files) = rfd::FileDialog::new().pick_files() {
+                    return Command::perform(async move { Message::FilesSelected(files) }, |msg| msg);
+                }
+            }
+            Message::BrowseFolder => {
+                if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                    if self.archive_folders {
+                        return Command::perform(async move { Message::FilesSelected(vec![folder]) }, |msg| msg);
+                    }
+                    match batch::files_in(&folder) {
+                        Ok(files) => return Command::perform(async move { Message::FilesSelected(files) }, |msg| msg),
+                        Err(e) => {
+                            self.message = Status::Error(format!("Cannot read the folder {}: {}", folder.display(), e));
+                        }
+                    }
                 }
             }
-            Message::FileSelected(file) => {
-                self.file_path = Some(file);
+            Message::ArchiveFoldersToggled(archive) => {
+                self.archive_folders = archive;
+            }
+            Message::FilesSelected(files) => {
+                self.add_files(files);
+            }
+            Message::FilesHovered(hovered) => {
+                self.files_hovered = hovered;
+            }
+            Message::FileDropped(path) => {
+                self.files_hovered = false;
+                // Like the browse buttons, dropping only adds files between batches.
+                if self.busy() {
+                    self.message = Status::Info(String::from("Files can be added once processing has finished"));
+                } else if path.is_dir() && !self.archive_folders {
+                    match batch::files_in(&path) {
+                        Ok(files) => self.add_files(files),
+                        Err(e) => {
+                            self.message = Status::Error(format!("Cannot read the folder {}: {}", path.display(), e));
+                        }
+                    }
+                } else {
+                    self.add_files(vec![path]);
+                }
+            }
+            Message::ClearFiles => {
+                if !self.busy() {
+                    self.queue.clear();
+                    self.header = None;
+                    self.metadata = None;
+                    self.message = Status::default();
+                }
             }
             Message::PasswordChanged(password) => {
-                self.password = password;
+                self.password = Zeroizing::new(password);
             }
-            Message::ModeChanged(mode) => {
-                self.mode = mode;
-            }
-            Message::ProcessFile => {
-                if let Some(file_path) = &self.file_path {
-                    let key = password_to_key(&self.password);
-
-                    let iv = [0u8; 16];
-                    let key = GenericArray::from_slice(&key);
-                    let cipher = Aes256::new(&key);
-                    let iv = GenericArray::from_slice(&iv);
-                    let block_mode = Cbc::<Aes256, Pkcs7>::new(cipher, iv);
-
-                    let mut file = File::open(file_path).expect("Failed to open file");
-                    let mut file_content = Vec::new();
-                    file.read_to_end(&mut file_content).expect("Failed to read file");
-
-                    let result = match self.mode {
-                        Mode::Encrypt => {
-                            let encrypted = block_mode.encrypt_vec(&file_content);
-                            encrypted
+            Message::ConfirmChanged(password) => {
+                self.confirm_password = Zeroizing::new(password);
+            }
+            Message::ForgetPasswordToggled(forget) => {
+                self.forget_password = forget;
+            }
+            Message::AddKeyfiles => {
+                if let Some(paths) = rfd::FileDialog::new().pick_files() {
+                    for path in paths {
+                        if !self.keyfiles.contains(&path) {
+                            self.keyfiles.push(path);
                         }
-                        Mode::Decrypt => {
-                            let decrypted = block_mode.decrypt_vec(&file_content).unwrap();
-                            decrypted
+                    }
+                }
+            }
+            Message::ClearKeyfiles => {
+                self.keyfiles.clear();
+            }
+            Message::GenerateKeyfile => {
+                if let Some(path) = rfd::FileDialog::new().set_file_name("keyfile.key").save_file() {
+                    self.message = match keyfile::generate(&path) {
+                        Ok(()) => {
+                            if !self.busy() && !self.keyfiles.contains(&path) {
+                                self.keyfiles.push(path.clone());
+                            }
+                            Status::Info(format!(
+                                "Keyfile saved as {}. Keep a copy in a safe place: files encrypted with it cannot be decrypted without it",
+                                path.display()
+                            ))
                         }
+                        Err(e) => Status::Error(format!("Cannot create the keyfile: {}", e)),
                     };
-
-                    let output_file_path = format!("{}_{}", file_path.display(), self.mode);
-                    let mut output_file = File::create(&output_file_path).expect("Failed to create output file");
-                    output_file.write_all(&result).expect("Failed to write output file");
-
-                    // Updated this line to set the message text instead of printing it to the console
-                    self.message = format!("{}ed file saved as: {}", self.mode, output_file_path);
+                }
+            }
+            Message::RecipientInputChanged(text) => {
+                self.recipient_input = text;
+            }
+            Message::AddRecipient => {
+                match self.recipient_input.parse::<Recipient>() {
+                    Ok(key) => {
+                        if !self.recipients.contains(&key) {
+                            self.recipients.push(key);
+                        }
+                        self.recipient_input.clear();
+                    }
+                    Err(e) => self.message = Status::Error(e.to_string()),
+                }
+            }
+            Message::LoadRecipients => {
+                if let Some(path) = rfd::FileDialog::new().pick_file() {
+                    match recipient::read_recipients(&path) {
+                        Ok(keys) => {
+                            for key in keys {
+                                if !self.recipients.contains(&key) {
+                                    self.recipients.push(key);
+                                }
+                            }
+                        }
+                        Err(e) => self.message = Status::Error(format!("Cannot read {}: {}", path.display(), e)),
+                    }
+                }
+            }
+            Message::ClearRecipients => {
+                self.recipients.clear();
+            }
+            Message::AddIdentities => {
+                if let Some(paths) = rfd::FileDialog::new().pick_files() {
+                    for path in paths {
+                        match recipient::read_identities(&path) {
+                            Ok(keys) => {
+                                for key in keys {
+                                    if !self.identities.contains(&key) {
+                                        self.identities.push(key);
+                                    }
+                                }
+                            }
+                            Err(e) => self.message = Status::Error(format!("Cannot read {}: {}", path.display(), e)),
+                        }
+                    }
+                }
+            }
+            Message::ClearIdentities => {
+                self.identities.clear();
+            }
+            Message::GenerateIdentity => {
+                if let Some(path) = rfd::FileDialog::new().set_file_name("identity.key").save_file() {
+                    let identity = Identity::generate();
+                    self.message = match recipient::write_identity(&path, &identity) {
+                        Ok(()) => {
+                            // Text in the window cannot be copied, but the input field's can.
+                            self.recipient_input = identity.recipient().to_string();
+                            if !self.busy() && !self.identities.contains(&identity) {
+                                self.identities.push(identity);
+                            }
+                            Status::Info(format!(
+                                "Key pair saved as {}. Your public key, for the people who send you files, is in the recipient field",
+                                path.display()
+                            ))
+                        }
+                        Err(e) => Status::Error(format!("Cannot create the key pair: {}", e)),
+                    };
+                }
+            }
+            Message::NewPasswordChanged(password) => {
+                self.new_password = Zeroizing::new(password);
+            }
+            Message::NewConfirmChanged(password) => {
+                self.new_confirm_password = Zeroizing::new(password);
+            }
+            Message::AddNewKeyfiles => {
+                if let Some(paths) = rfd::FileDialog::new().pick_files() {
+                    for path in paths {
+                        if !self.new_keyfiles.contains(&path) {
+                            self.new_keyfiles.push(path);
+                        }
+                    }
+                }
+            }
+            Message::ClearNewKeyfiles => {
+                self.new_keyfiles.clear();
+            }
+            Message::AddPasswordSlot => {
+                if self.new_password != self.new_confirm_password {
+                    self.message = Status::Error(String::from("The new passwords do not match"));
+                } else {
+                    let (password, keyfiles) = (self.new_password.clone(), self.new_keyfiles.clone());
+                    return self.update_slots(move |path, unlock, options| {
+                        let slot = NewSlot::Password { password: &password, keyfiles: &keyfiles, kdf: Kdf::default() };
+                        keyslot::add_slot(path, unlock, options, &slot)
+                    });
+                }
+            }
+            Message::AddRecipientSlot => match self.recipient_input.parse::<Recipient>() {
+                Ok(key) => {
+                    return self.update_slots(move |path, unlock, options| {
+                        keyslot::add_slot(path, unlock, options, &NewSlot::Recipient(key))
+                    });
+                }
+                Err(e) => self.message = Status::Error(e.to_string()),
+            },
+            Message::RemoveSlot(index) => {
+                let confirmed = rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Warning)
+                    .set_title("Remove key slot?")
+                    .set_description(&format!(
+                        "The file will no longer open with key slot {}. Copies of the file made before still do. Continue?",
+                        index + 1
+                    ))
+                    .set_buttons(rfd::MessageButtons::YesNo)
+                    .show();
+                if confirmed {
+                    return self.update_slots(move |path, unlock, options| keyslot::remove_slot(path, unlock, options, index));
+                }
+            }
+            Message::SlotsUpdated(result) => {
+                self.updating_slots = false;
+                match result {
+                    Ok(header) => {
+                        self.message = Status::Info(format!("Key slots updated. {}", describe(&header)));
+                        self.header = Some(header);
+                        self.new_password = Zeroizing::default();
+                        self.new_confirm_password = Zeroizing::default();
+                        self.new_keyfiles.clear();
+                    }
+                    Err(e) => self.message = Status::Error(format!("Cannot change the key slots: {}", e)),
+                }
+            }
+            Message::ShowMetadata => {
+                if let (false, [item]) = (self.busy(), self.queue.items()) {
+                    let path = item.path.clone();
+                    self.message = Status::Info(format!("Reading the encrypted details of {}...", path.display()));
+                    let password = self.password.clone();
+                    let options = DecryptOptions {
+                        keyfiles: self.keyfiles.clone(),
+                        identities: self.identities.clone(),
+                        ..DecryptOptions::default()
+                    };
+                    return Command::perform(
+                        async move {
+                            let result = files::read_metadata(&path, &password, &options);
+                            (path, result)
+                        },
+                        |(path, result)| Message::MetadataRead(path, result),
+                    );
+                }
+            }
+            Message::MetadataRead(path, result) => {
+                // The selection may have changed while the key was derived.
+                if let [item] = self.queue.items() {
+                    if item.path == path {
+                        match result {
+                            Ok(Some(metadata)) => {
+                                self.metadata = Some(metadata);
+                                self.message = Status::Info(format!("Read the encrypted details of {}", path.display()));
+                            }
+                            Ok(None) => self.message = Status::Info(format!("{} has no encrypted details", path.display())),
+                            Err(e) => self.message = Status::Error(format!("Cannot read the encrypted details: {}", e)),
+                        }
+                    }
+                }
+            }
+            Message::ModeChanged(mode) => {
+                // The whole batch is processed in the mode it was started in.
+                if !self.busy() && self.mode != mode {
+                    self.mode = mode;
+                    self.queue.reset_outputs();
+                }
+            }
+            Message::CompressionChanged(compression) => {
+                if !self.busy() {
+                    self.compression = compression;
+                }
+            }
+            Message::ExtensionChanged(extension) => {
+                self.extension = extension;
+            }
+            Message::ChooseOutputDir => {
+                if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                    self.output_dir = Some(folder);
+                }
+            }
+            Message::ResetOutputDir => {
+                self.output_dir = None;
+            }
+            Message::SaveAs => {
+                if let (false, [item]) = (self.busy(), self.queue.items()) {
+                    let suggested = self.suggested_output(&item.path);
+                    let mut dialog = rfd::FileDialog::new();
+                    if let Some(dir) = suggested.parent() {
+                        dialog = dialog.set_directory(dir);
+                    }
+                    if let Some(name) = suggested.file_name().and_then(|n| n.to_str()) {
+                        dialog = dialog.set_file_name(name);
+                    }
+                    if let Some(output) = dialog.save_file() {
+                        self.queue.set_output(0, output);
+                    }
+                }
+            }
+            Message::IfExistsChanged(if_exists) => {
+                self.if_exists = if_exists;
+            }
+            Message::ResolveConflict(choice) => {
+                if let Some(conflict) = self.conflict.take() {
+                    if !self.resolve(conflict, choice) {
+                        self.start_next_job();
+                    }
+                }
+            }
+            Message::RemoveOriginalsToggled(remove) => {
+                if !self.busy() {
+                    self.remove_originals = remove;
+                }
+            }
+            Message::HideNamesToggled(hide) => {
+                if !self.busy() {
+                    self.hide_names = hide;
+                }
+            }
+            Message::ProcessFiles => {
+                if !self.busy()
+                    && self.password_ready()
+                    && self.confirm_encrypting_again()
+                    && self.confirm_removing_originals()
+                    && self.queue.prepare() > 0
+                {
+                    self.start_next_job();
+                }
+            }
+            Message::CancelProcessing => {
+                if let Some(job) = &self.job {
+                    job.cancel.store(true, Ordering::Relaxed);
+                    self.message = Status::Info(String::from("Cancelling..."));
+                }
+            }
+            Message::Worker(worker::Event::Progress { processed, total }) => {
+                self.progress.processed = processed;
+                self.progress.total = total;
+            }
+            Message::Worker(worker::Event::Done(outcome)) => {
+                if let Some(job) = self.job.take() {
+                    match outcome {
+                        worker::Outcome::Finished(output) => self.queue.finish_current(ItemStatus::Finished(output)),
+                        worker::Outcome::Verified(header) => self.queue.finish_current(ItemStatus::Verified(header)),
+                        worker::Outcome::Skipped => self.queue.finish_current(ItemStatus::Skipped),
+                        worker::Outcome::Conflict(output) => {
+                            self.message = Status::Info(format!("{} already exists. Replace it?", output.display()));
+                            self.conflict = Some(Conflict { input: job.input, output });
+                            return Command::none();
+                        }
+                        worker::Outcome::Failed(error) => self.queue.finish_current(ItemStatus::Failed(error)),
+                        worker::Outcome::Cancelled => {
+                            self.queue.finish_current(ItemStatus::Cancelled);
+                            self.finish_batch(true);
+                            return Command::none();
+                        }
+                    }
+                    self.start_next_job();
                 }
             }
         }
@@ -169,37 +680,425 @@ impl Application for App {
         Command::none()
     }
 
-    fn view(&mut self) -> Element<Message> {
-        let file_path = self.file_path.as_ref().map_or("No file selected", |p| p.to_str().unwrap());
+    //Window events are always listened to, for files dragged onto the window. While a
+    // job is running its worker is subscribed to as well.
+    fn subscription(&self) -> Subscription<Message> {
+        let drops = subscription::events_with(|event, _| match event {
+            Event::Window(window::Event::FileHovered(_)) => Some(Message::FilesHovered(true)),
+            Event::Window(window::Event::FilesHoveredLeft) => Some(Message::FilesHovered(false)),
+            Event::Window(window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
+            _ => None,
+        });
+        match &self.job {
+            Some(job) => Subscription::batch(vec![drops, worker::subscription(job).map(Message::Worker)]),
+            None => drops,
+        }
+    }
+
+    fn view(&mut self) -> Element<'_, Message> {
+        let busy = self.busy();
+        let password_ready = self.password_ready();
+
+        // display() never fails, unlike to_str(), which returns None for paths that are
+        // not valid UTF-8 (possible on Linux and Windows).
+        let mut file_list = Scrollable::new(&mut self.queue_scroll).height(Length::Units(120)).spacing(5);
+        if self.queue.is_empty() {
+            file_list = file_list.push(Text::new("No files selected. Drop files or folders here, or browse for them."));
+        }
+        for item in self.queue.items() {
+            let status = match &item.status {
+                ItemStatus::Pending => Text::new("waiting"),
+                ItemStatus::Running => Text::new("working..."),
+                ItemStatus::Finished(output) => Text::new(format!(
+                    "saved as {}",
+                    output.file_name().unwrap_or(output.as_os_str()).to_string_lossy()
+                )),
+                ItemStatus::Verified(_) => Text::new("OK, intact"),
+                ItemStatus::Cancelled => Text::new("cancelled"),
+                ItemStatus::Skipped => Text::new("skipped, the output exists"),
+                ItemStatus::Failed(error) => Text::new(format!("failed: {}", error)).color(ERROR_COLOR),
+            };
+            file_list = file_list.push(
+                Row::new()
+                    .spacing(10)
+                    .push(Text::new(item.path.display().to_string()).width(Length::FillPortion(2)))
+                    .push(status.width(Length::FillPortion(1))),
+            );
+        }
 
-        let browse_button = Button::new(&mut self.browse_button, Text::new("Browse file"))
-            .on_press(Message::BrowseFile);
+        let mut browse_button = Button::new(&mut self.browse_button, Text::new("Browse files"));
+        let mut browse_folder_button = Button::new(&mut self.browse_folder_button, Text::new("Browse folder"));
+        let mut clear_button = Button::new(&mut self.clear_button, Text::new("Clear"));
+        if !busy {
+            browse_button = browse_button.on_press(Message::BrowseFiles);
+            browse_folder_button = browse_folder_button.on_press(Message::BrowseFolder);
+            clear_button = clear_button.on_press(Message::ClearFiles);
+        }
+        let browse_buttons = Row::new()
+            .spacing(10)
+            .push(browse_button)
+            .push(browse_folder_button)
+            .push(clear_button)
+            .push(Checkbox::new(
+                self.archive_folders,
+                "Encrypt folders as a single file",
+                Message::ArchiveFoldersToggled,
+            ));
 
         let password_input = TextInput::new(&mut self.password_input, "Enter password", &self.password, Message::PasswordChanged)
             .password();
+        let mut password_section = Column::new().spacing(10).push(password_input);
+
+        // A typo in a new password would make the file impossible to decrypt, so encrypting
+        // asks for it twice, and shows how easy it would be to guess.
+        if self.mode == Mode::Encrypt {
+            let confirm_input = TextInput::new(
+                &mut self.confirm_input,
+                "Confirm password",
+                &self.confirm_password,
+                Message::ConfirmChanged,
+            )
+            .password();
+            let estimate = strength::estimate(&self.password);
+            let strength_text = if self.password.is_empty() && self.keyfiles.is_empty() && self.recipients.is_empty() {
+                Text::new("Enter a password")
+            } else if self.password.is_empty() && self.keyfiles.is_empty() {
+                Text::new("No password: only the recipients can open the files")
+            } else if self.password.is_empty() {
+                Text::new("No password: the keyfiles alone protect the files")
+            } else {
+                Text::new(format!("Strength: {} (about {:.0} bits)", estimate.strength, estimate.bits))
+                    .color(strength_color(estimate.strength))
+            };
+            let level = if self.password.is_empty() { 0.0 } else { estimate.strength as u8 as f32 + 1.0 };
+            password_section = password_section.push(confirm_input).push(
+                Row::new()
+                    .spacing(10)
+                    .push(ProgressBar::new(0.0..=5.0, level).width(Length::Units(100)).height(Length::Units(10)))
+                    .push(strength_text),
+            );
+            if !self.confirm_password.is_empty() && self.confirm_password != self.password {
+                password_section = password_section.push(Text::new("The passwords do not match").color(ERROR_COLOR));
+            }
+        }
+
+        let keyfiles = match self.keyfiles.as_slice() {
+            [] => String::from("Keyfiles: none"),
+            paths => {
+                let names: Vec<_> = paths
+                    .iter()
+                    .map(|path| path.file_name().unwrap_or(path.as_os_str()).to_string_lossy())
+                    .collect();
+                format!("Keyfiles: {}", names.join(", "))
+            }
+        };
+        // The keyfiles apply to the whole batch, so they are only changed between batches.
+        let mut add_keyfiles_button = Button::new(&mut self.add_keyfiles_button, Text::new("Add keyfiles"));
+        let mut clear_keyfiles_button = Button::new(&mut self.clear_keyfiles_button, Text::new("Clear"));
+        if !busy {
+            add_keyfiles_button = add_keyfiles_button.on_press(Message::AddKeyfiles);
+            clear_keyfiles_button = clear_keyfiles_button.on_press(Message::ClearKeyfiles);
+        }
+        password_section = password_section.push(
+            Row::new()
+                .spacing(10)
+                .push(Text::new(keyfiles))
+                .push(add_keyfiles_button)
+                .push(clear_keyfiles_button)
+                .push(Button::new(&mut self.generate_keyfile_button, Text::new("Generate keyfile...")).on_press(Message::GenerateKeyfile)),
+        );
+        password_section = password_section.push(Checkbox::new(
+            self.forget_password,
+            "Clear the password when processing is done",
+            Message::ForgetPasswordToggled,
+        ));
+
+        // Public keys: whom new files are encrypted to, or which secret keys to decrypt with.
+        // The recipient field is shown in both modes, since a new public key is put there to
+        // be copied.
+        let recipient_input = TextInput::new(
+            &mut self.recipient_input_state,
+            "Public key (age1...)",
+            &self.recipient_input,
+            Message::RecipientInputChanged,
+        )
+        .on_submit(Message::AddRecipient);
+        let mut key_section = Column::new().spacing(10);
+        if self.mode == Mode::Encrypt {
+            let recipients = if self.recipients.is_empty() {
+                String::from("Recipients: none, the password is used")
+            } else {
+                let keys: Vec<_> = self.recipients.iter().map(|key| abbreviate(&key.to_string())).collect();
+                format!("Recipients: {} (each of them, or the password, opens the files)", keys.join(", "))
+            };
+            let mut add_button = Button::new(&mut self.add_recipient_button, Text::new("Add"));
+            let mut load_button = Button::new(&mut self.load_recipients_button, Text::new("Load..."));
+            let mut clear_button = Button::new(&mut self.clear_recipients_button, Text::new("Clear"));
+            if !busy {
+                add_button = add_button.on_press(Message::AddRecipient);
+                load_button = load_button.on_press(Message::LoadRecipients);
+                clear_button = clear_button.on_press(Message::ClearRecipients);
+            }
+            key_section = key_section
+                .push(Text::new(recipients))
+                .push(Row::new().spacing(10).push(recipient_input).push(add_button).push(load_button).push(clear_button));
+        } else {
+            let identities = match self.identities.len() {
+                0 => String::from("Identities: none (needed for files encrypted to you)"),
+                1 => String::from("Identities: 1 secret key"),
+                n => format!("Identities: {} secret keys", n),
+            };
+            let mut add_button = Button::new(&mut self.add_identities_button, Text::new("Add identity files"));
+            let mut clear_button = Button::new(&mut self.clear_identities_button, Text::new("Clear"));
+            if !busy {
+                add_button = add_button.on_press(Message::AddIdentities);
+                clear_button = clear_button.on_press(Message::ClearIdentities);
+            }
+            key_section = key_section
+                .push(Row::new().spacing(10).push(Text::new(identities)).push(add_button).push(clear_button))
+                .push(recipient_input);
+        }
+        key_section = key_section.push(
+            Button::new(&mut self.generate_identity_button, Text::new("Generate key pair...")).on_press(Message::GenerateIdentity),
+        );
+
+        // The key slots of a single selected file. The password, keyfiles and identities
+        // above open it; the new password and keyfiles below, or the public key in the
+        // recipient field, are what a new slot is made of.
+        let mut slot_section = Column::new().spacing(10);
+        if let Some(Header { slots, .. }) = &self.header {
+            let enabled = !busy && self.mode != Mode::Encrypt;
+            self.slot_buttons.resize_with(slots.len(), button::State::default);
+            slot_section = slot_section.push(Text::new("Key slots (opened with the password, keyfiles or identities above):"));
+            for (i, (slot, state)) in slots.iter().zip(self.slot_buttons.iter_mut()).enumerate() {
+                let mut remove_button = Button::new(state, Text::new("Remove"));
+                if enabled && slots.len() > 1 {
+                    remove_button = remove_button.on_press(Message::RemoveSlot(i));
+                }
+                slot_section = slot_section.push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new(format!("{}. {}", i + 1, slot)).width(Length::Fill))
+                        .push(remove_button),
+                );
+            }
 
-        let mode_radio = Row::new()
+            let new_keyfiles = match self.new_keyfiles.len() {
+                0 => String::from("New keyfiles: none"),
+                n => format!("New keyfiles: {}", n),
+            };
+            let mut add_keyfiles_button = Button::new(&mut self.add_new_keyfiles_button, Text::new("Add"));
+            let mut clear_keyfiles_button = Button::new(&mut self.clear_new_keyfiles_button, Text::new("Clear"));
+            let mut add_password_button = Button::new(&mut self.add_password_slot_button, Text::new("Add password"));
+            let mut add_recipient_button = Button::new(&mut self.add_recipient_slot_button, Text::new("Add public key"));
+            if enabled {
+                add_keyfiles_button = add_keyfiles_button.on_press(Message::AddNewKeyfiles);
+                clear_keyfiles_button = clear_keyfiles_button.on_press(Message::ClearNewKeyfiles);
+                if !self.new_password.is_empty() || !self.new_keyfiles.is_empty() {
+                    add_password_button = add_password_button.on_press(Message::AddPasswordSlot);
+                }
+                if !self.recipient_input.is_empty() {
+                    add_recipient_button = add_recipient_button.on_press(Message::AddRecipientSlot);
+                }
+            }
+            slot_section = slot_section
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(
+                            TextInput::new(&mut self.new_password_input, "New password", &self.new_password, Message::NewPasswordChanged)
+                                .password(),
+                        )
+                        .push(
+                            TextInput::new(
+                                &mut self.new_confirm_input,
+                                "Confirm new password",
+                                &self.new_confirm_password,
+                                Message::NewConfirmChanged,
+                            )
+                            .password(),
+                        ),
+                )
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new(new_keyfiles))
+                        .push(add_keyfiles_button)
+                        .push(clear_keyfiles_button)
+                        .push(add_password_button)
+                        .push(add_recipient_button),
+                );
+        }
+
+        // What the header of the selected file says, which anyone can read, and what its
+        // encrypted metadata says once it has been read with the password.
+        let mut details_section = Column::new().spacing(5);
+        if let Some(header) = &self.header {
+            let metadata = self.metadata.as_ref().filter(|_| header.metadata.is_some());
+            let mut details = header_details(header, metadata);
+            if let Some(metadata) = metadata {
+                details.extend(metadata_details(metadata));
+            }
+            for (label, value) in details {
+                details_section = details_section
+                    .push(Row::new().spacing(10).push(Text::new(label).width(Length::Units(140))).push(Text::new(value)));
+            }
+            if header.metadata.is_some() && self.metadata.is_none() {
+                let mut show_button = Button::new(&mut self.show_metadata_button, Text::new("Show encrypted details"));
+                if !busy {
+                    show_button = show_button.on_press(Message::ShowMetadata);
+                }
+                details_section = details_section.push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new("The name, time and permissions are encrypted; the password above opens them."))
+                        .push(show_button),
+                );
+            }
+        }
+
+        let mut mode_radio = Row::new()
+            .spacing(10)
             .push(Radio::new(Mode::Encrypt, "Encrypt", Some(self.mode), Message::ModeChanged))
-            .push(Radio::new(Mode::Decrypt, "Decrypt", Some(self.mode), Message::ModeChanged));
+            .push(Radio::new(Mode::Decrypt, "Decrypt", Some(self.mode), Message::ModeChanged))
+            .push(Radio::new(Mode::Verify, "Verify", Some(self.mode), Message::ModeChanged));
+        // Decrypting finds the compression in the file, so there is nothing to choose.
+        if self.mode == Mode::Encrypt {
+            mode_radio = mode_radio.push(Text::new("Compression:")).push(PickList::new(
+                &mut self.compression_list,
+                &COMPRESSION_LEVELS[..],
+                Some(self.compression),
+                Message::CompressionChanged,
+            ));
+        }
+
+        let extension_input = TextInput::new(
+            &mut self.extension_input,
+            naming::DEFAULT_EXTENSION,
+            &self.extension,
+            Message::ExtensionChanged,
+        )
+        .width(Length::Units(80));
+        let output_dir = match &self.output_dir {
+            Some(dir) => format!("Output folder: {}", dir.display()),
+            None => String::from("Output folder: next to each file"),
+        };
+        // Verifying writes nothing, so there is no output to set up.
+        let output_settings = if self.mode == Mode::Verify {
+            Column::new().push(Text::new(
+                "Each file is decrypted in memory and checked against its authentication tags; \
+                 no decrypted data is written to disk.",
+            ))
+        } else {
+            let mut output_settings = Column::new()
+                .spacing(10)
+                .push(Row::new().spacing(10).push(Text::new("Extension for encrypted files:")).push(extension_input))
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new("If the output exists:"))
+                        .push(Radio::new(IfExists::Ask, "Ask", Some(self.if_exists), Message::IfExistsChanged))
+                        .push(Radio::new(IfExists::Overwrite, "Replace", Some(self.if_exists), Message::IfExistsChanged))
+                        .push(Radio::new(IfExists::Rename, "Rename", Some(self.if_exists), Message::IfExistsChanged))
+                        .push(Radio::new(IfExists::Skip, "Skip", Some(self.if_exists), Message::IfExistsChanged)),
+                )
+                .push(
+                    Row::new()
+                        .spacing(10)
+                        .push(Text::new(output_dir))
+                        .push(Button::new(&mut self.output_dir_button, Text::new("Choose")).on_press(Message::ChooseOutputDir))
+                        .push(Button::new(&mut self.same_dir_button, Text::new("Reset")).on_press(Message::ResetOutputDir)),
+                );
+            if self.mode == Mode::Encrypt {
+                output_settings = output_settings.push(Checkbox::new(
+                    self.hide_names,
+                    "Do not store the original names, not even encrypted",
+                    Message::HideNamesToggled,
+                ));
+                output_settings = output_settings.push(Checkbox::new(
+                    self.remove_originals,
+                    "Remove the originals once they are encrypted and checked",
+                    Message::RemoveOriginalsToggled,
+                ));
+                if self.remove_originals {
+                    output_settings = output_settings.push(Text::new(
+                        "Originals are overwritten before they are deleted, but SSDs, copy-on-write file systems, \
+                         snapshots and backups may still keep their contents.",
+                    ));
+                }
+            }
+            output_settings
+        };
 
-        let process_button = Button::new(&mut self.process_button, Text::new("Process file"))
-            .on_press(Message::ProcessFile);
+        // Only one batch is processed at a time, so the button does nothing while a job runs.
+        let mut process_button = Button::new(&mut self.process_button, Text::new("Process files"));
+        if !busy && password_ready && !self.queue.is_empty() {
+            process_button = process_button.on_press(Message::ProcessFiles);
+        }
+        // A save dialog picks a single path, so it is only offered for a single file.
+        let mut save_as_button = Button::new(&mut self.save_as_button, Text::new("Save as..."));
+        if !busy && self.queue.len() == 1 && self.mode != Mode::Verify {
+            save_as_button = save_as_button.on_press(Message::SaveAs);
+        }
 
-        let message_text = Text::new(&self.message); // Added this line to display the message text
+        // Added this line to display the message text
+        let message_text = match &self.message {
+            Status::Info(text) => Text::new(text),
+            Status::Error(text) => Text::new(text).color(ERROR_COLOR),
+        };
 
-        let content = Column::new()
+        let mut content = Column::new()
             .padding(20)
             .spacing(20)
-            .push(Text::new(file_path))
-            .push(browse_button)
-            .push(password_input)
+            .push(
+                Container::new(file_list)
+                    .width(Length::Fill)
+                    .padding(5)
+                    .style(DropZone { hovered: self.files_hovered }),
+            )
+            .push(browse_buttons)
+            .push(password_section)
+            .push(key_section)
+            .push(details_section)
+            .push(slot_section)
             .push(mode_radio)
-            .push(process_button)
-            .push(message_text); // Added this line to
+            .push(output_settings)
+            .push(Row::new().spacing(10).push(process_button).push(save_as_button));
+
+        if self.conflict.is_some() {
+            content = content.push(
+                Row::new()
+                    .spacing(10)
+                    .push(Button::new(&mut self.overwrite_button, Text::new("Replace")).on_press(Message::ResolveConflict(IfExists::Overwrite)))
+                    .push(Button::new(&mut self.rename_button, Text::new("Keep both")).on_press(Message::ResolveConflict(IfExists::Rename)))
+                    .push(Button::new(&mut self.skip_button, Text::new("Skip")).on_press(Message::ResolveConflict(IfExists::Skip))),
+            );
+        }
+
+        if self.job.is_some() {
+            let progress = &self.progress;
+            let mut status = format!(
+                "File {} of {}: {} of {}",
+                self.queue.current().unwrap_or(0),
+                self.queue.len(),
+                format_bytes(progress.processed),
+                format_bytes(progress.total)
+            );
+            if let Some(remaining) = progress.remaining() {
+                status.push_str(&format!(", about {} s left", remaining.as_secs() + 1));
+            }
+            let cancel_button = Button::new(&mut self.cancel_button, Text::new("Cancel"))
+                .on_press(Message::CancelProcessing);
+            content = content
+                .push(ProgressBar::new(0.0..=progress.total.max(1) as f32, progress.processed as f32))
+                .push(Row::new().spacing(20).push(Text::new(status)).push(cancel_button));
+        }
+
+        content = content.push(message_text); // Added this line to
 
         // Convert dimensions from millimeters to pixels (assuming 96 DPI)
-        let width_px: f32 = (100.0 / 25.4) * 96.0;
-        let height_px: f32 = (100.0 / 25.4) * 96.0;
+        let width_px: f32 = (160.0 / 25.4) * 96.0;
+        let height_px: f32 = (180.0 / 25.4) * 96.0;
 
         Container::new(content)
             .width(Length::Units(width_px.round() as u16))
@@ -210,22 +1109,421 @@ impl Application for App {
     }
 }
 
-//This function takes a string (password) as the parameter and returns an array of 32
-// 8-bit unsigned integers (key). The function first initializes an array of 32 8-bit
-// unsigned integers filled with 0s to be used as the key. It then takes the password
-// string and converts it to an array of bytes (password_bytes). The function then
-// iterates through the elements of password_bytes and XORs each element with the
-// corresponding element in the key array (i % 32). This process creates a new key
-// that is a combination of the original password and the zero-filled array. The new
-// key is then finally returned.
-fn password_to_key(password: &str) -> [u8; 32] {
-    let mut key = [0u8; 32];
-    let password_bytes = password.as_bytes();
-    for (i, byte) in password_bytes.iter().enumerate() {
-        key[i % 32] ^= *byte;
+impl App {
+    //Queues files and picks the mode that suits them: Decrypt if every file still to be
+    // processed is one of ours, Encrypt if none is. With a mix the user's choice stands.
+    // A single encrypted file also gets a description of how it was encrypted.
+    fn add_files(&mut self, files: Vec<PathBuf>) {
+        self.queue.add(files);
+        self.header = None;
+        self.metadata = None;
+
+        let (mut total, mut encrypted) = (0, 0);
+        for item in self.queue.unfinished() {
+            total += 1;
+            encrypted += item.encrypted as usize;
+        }
+        let mode = match encrypted {
+            _ if total == 0 => return,
+            0 => Mode::Encrypt,
+            // Verifying is for encrypted files too, so it is kept.
+            n if n == total && self.mode == Mode::Verify => Mode::Verify,
+            n if n == total => Mode::Decrypt,
+            _ => return,
+        };
+        if mode != self.mode {
+            self.mode = mode;
+            self.queue.reset_outputs();
+        }
+
+        if let [item] = self.queue.items() {
+            if item.encrypted {
+                self.message = match files::read_header(&item.path) {
+                    Ok(header) => {
+                        let description = describe(&header);
+                        self.header = Some(header);
+                        Status::Info(description)
+                    }
+                    Err(e) => Status::Error(format!("{} looks encrypted but cannot be read: {}", item.path.display(), e)),
+                };
+            }
+        }
+    }
+
+    //New files need a password typed the same way twice, a keyfile or recipients, or any
+    // combination of them. Decrypting and verifying accept any password, including an empty
+    // one, which the first release allowed.
+    fn password_ready(&self) -> bool {
+        self.mode != Mode::Encrypt
+            || (self.password == self.confirm_password
+                && (!self.password.is_empty() || !self.keyfiles.is_empty() || !self.recipients.is_empty()))
+    }
+
+    //Runs `change` on the key slots of the selected file in the background, opening the
+    // file with the password, keyfiles and identities entered, and reports the new header
+    // through SlotsUpdated. The KDF takes a moment for each password slot involved.
+    fn update_slots<F>(&mut self, change: F) -> Command<Message>
+    where
+        F: FnOnce(&Path, &str, &DecryptOptions) -> Result<Header, Error> + Send + 'static,
+    {
+        let path = match (self.busy(), self.queue.items()) {
+            (false, [item]) => item.path.clone(),
+            _ => return Command::none(),
+        };
+        self.updating_slots = true;
+        self.message = Status::Info(format!("Updating the key slots of {}...", path.display()));
+        let password = self.password.clone();
+        let options = DecryptOptions {
+            keyfiles: self.keyfiles.clone(),
+            identities: self.identities.clone(),
+            ..DecryptOptions::default()
+        };
+        Command::perform(async move { change(&path, &password, &options) }, Message::SlotsUpdated)
+    }
+
+    //Encrypting a file that is already encrypted is almost always a mistake (the result
+    // has to be decrypted twice), so the user is asked first. Returns whether to go on.
+    fn confirm_encrypting_again(&self) -> bool {
+        let encrypted = self.queue.unfinished().filter(|item| item.encrypted).count();
+        if self.mode != Mode::Encrypt || encrypted == 0 {
+            return true;
+        }
+        let description = match encrypted {
+            1 => String::from("One of the files is already encrypted."),
+            n => format!("{} of the files are already encrypted.", n),
+        };
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Encrypt again?")
+            .set_description(&format!("{} A file encrypted twice has to be decrypted twice as well. Continue?", description))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show()
+    }
+
+    //Removing the originals cannot be undone, so the user is asked first, and reminded
+    // that the overwrite is not a guarantee. Returns whether to go on.
+    fn confirm_removing_originals(&self) -> bool {
+        if self.mode != Mode::Encrypt || !self.remove_originals {
+            return true;
+        }
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("Remove the originals?")
+            .set_description(
+                "Each original file is overwritten and deleted once its encrypted copy has been decrypted and \
+                 found to match it. Make sure you can decrypt the files: without the password, keyfiles or \
+                 secret key they are lost. On SSDs, copy-on-write file systems and in snapshots or backups \
+                 the original contents may survive the overwrite. Continue?",
+            )
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show()
+    }
+
+    //Starts the job for the next file in the queue. Files whose output already exists are
+    // handled as the IfExists setting says, which may mean stopping to ask the user. When
+    // there is no file left, the batch is over and its summary is shown instead. Verifying
+    // writes no output, so it never has to ask. A decrypted file is named after the
+    // original, which is only known once the worker has opened it, so unless the user
+    // chose the output the worker deals with an existing one.
+    fn start_next_job(&mut self) {
+        while let Some(item) = self.queue.start_next() {
+            if self.mode == Mode::Verify || (self.mode == Mode::Decrypt && item.output.is_none()) {
+                self.start_job(item.path, None, self.mode == Mode::Decrypt && self.if_exists == IfExists::Overwrite);
+                return;
+            }
+            let output = item.output.unwrap_or_else(|| self.naming().encrypted_path(&item.path));
+            if fs::symlink_metadata(&output).is_err() {
+                self.start_job(item.path, Some(output), false);
+                return;
+            }
+            if self.if_exists == IfExists::Ask {
+                self.message = Status::Info(format!("{} already exists. Replace it?", output.display()));
+                self.conflict = Some(Conflict { input: item.path, output });
+                return;
+            }
+            if self.resolve(Conflict { input: item.path, output }, self.if_exists) {
+                return;
+            }
+        }
+        self.finish_batch(false);
+    }
+
+    //Shows the summary of the batch that just ended and, if the user asked for it, forgets
+    // the password. The old value is wiped as it is replaced.
+    fn finish_batch(&mut self, cancelled: bool) {
+        self.message = self.summary(cancelled);
+        if self.forget_password {
+            self.password = Zeroizing::default();
+            self.confirm_password = Zeroizing::default();
+        }
+    }
+
+    //Deals with a file whose output exists. Returns whether a job was started for it.
+    fn resolve(&mut self, conflict: Conflict, choice: IfExists) -> bool {
+        match choice {
+            IfExists::Overwrite => self.start_job(conflict.input, Some(conflict.output), true),
+            IfExists::Rename => self.start_job(conflict.input, Some(naming::unused_path(&conflict.output)), false),
+            IfExists::Skip | IfExists::Ask => {
+                self.queue.finish_current(ItemStatus::Skipped);
+                return false;
+            }
+        }
+        true
+    }
+
+    fn start_job(&mut self, input: PathBuf, output: Option<PathBuf>, overwrite: bool) {
+        self.message = Status::Info(format!("{}ing {}...", self.mode, input.display()));
+        self.next_job_id += 1;
+        self.job = Some(worker::Job {
+            id: self.next_job_id,
+            mode: self.mode,
+            input,
+            output,
+            naming: self.naming(),
+            if_exists: self.if_exists,
+            overwrite,
+            password: self.password.clone(),
+            keyfiles: self.keyfiles.clone(),
+            recipients: self.recipients.clone(),
+            identities: self.identities.clone(),
+            remove_original: self.mode == Mode::Encrypt && self.remove_originals,
+            compression: self.compression,
+            store_name: !self.hide_names,
+            cancel: Arc::new(AtomicBool::new(false)),
+        });
+        self.progress = Progress::default();
+    }
+
+    //Whether a batch is under way: a file is being processed, or the user is being asked
+    // about one. Settings that apply to the whole batch cannot be changed meanwhile. The
+    // same goes while the key slots of a file are being changed.
+    fn busy(&self) -> bool {
+        self.job.is_some() || self.conflict.is_some() || self.updating_slots
+    }
+
+    //Returns the path the "Save as" dialog suggests for `input`. The original name of an
+    // encrypted file is only known once it has been opened, so for decrypting this goes by
+    // the encrypted name.
+    fn suggested_output(&self, input: &Path) -> PathBuf {
+        match self.mode {
+            Mode::Encrypt => self.naming().encrypted_path(input),
+            Mode::Decrypt | Mode::Verify => self.naming().decrypted_path(input, None),
+        }
+    }
+
+    //The naming settings as they are entered in the window.
+    fn naming(&self) -> OutputNaming {
+        OutputNaming { extension: self.extension.clone(), directory: self.output_dir.clone() }
+    }
+
+    //Sums up the batch: a single file gets the path it was saved as (or its error),
+    // several files get counts, and an error status if any of them failed. Decrypted files
+    // from the first release get a warning, since nothing showed their contents are right.
+    fn summary(&self, cancelled: bool) -> Status {
+        if let [item] = self.queue.items() {
+            return match &item.status {
+                ItemStatus::Finished(output) if self.mode == Mode::Encrypt && self.remove_originals => Status::Info(format!(
+                    "Encrypted file saved as: {}; the original was removed",
+                    output.display()
+                )),
+                ItemStatus::Finished(output) if self.mode == Mode::Decrypt && files::is_legacy(&item.path) => Status::Info(format!(
+                    "Decrypted file saved as: {}. Warning: {}",
+                    output.display(),
+                    files::LEGACY_WARNING
+                )),
+                ItemStatus::Finished(output) if output.is_dir() => {
+                    Status::Info(format!("{} folder saved as: {}", self.mode.past_tense(), output.display()))
+                }
+                ItemStatus::Finished(output) => Status::Info(format!("{} file saved as: {}", self.mode.past_tense(), output.display())),
+                ItemStatus::Verified(header) => {
+                    Status::Info(format!("OK: {} decrypts and has not been modified. {}", item.path.display(), describe(header)))
+                }
+                ItemStatus::Failed(error) if self.mode == Mode::Verify => {
+                    Status::Error(format!("FAILED: {} did not verify: {}", item.path.display(), error))
+                }
+                ItemStatus::Failed(error) => Status::Error(format!("Cannot {} {}: {}", self.mode.verb(), item.path.display(), error)),
+                ItemStatus::Skipped => Status::Info(format!("Skipped {}", item.path.display())),
+                _ if self.mode == Mode::Verify => Status::Info(String::from("Cancelled")),
+                _ => Status::Info(String::from("Cancelled; the partial output file was removed")),
+            };
+        }
+        let summary = self.queue.summary();
+        let mut text = format!("{} {} of {} files", self.mode.past_tense(), summary.finished, self.queue.len());
+        if self.mode == Mode::Encrypt && self.remove_originals && summary.finished > 0 {
+            text.push_str(" and removed their originals");
+        }
+        if summary.skipped > 0 {
+            text.push_str(&format!(", {} skipped", summary.skipped));
+        }
+        let legacy = self
+            .queue
+            .items()
+            .iter()
+            .filter(|item| matches!(item.status, ItemStatus::Finished(_)) && files::is_legacy(&item.path))
+            .count();
+        if self.mode == Mode::Decrypt && legacy > 0 {
+            text.push_str(&format!(
+                "; {} came from the first release of the app, which did not protect files against changes, so check their contents",
+                legacy
+            ));
+        }
+        if summary.failed > 0 {
+            text.push_str(&format!(", {} failed", summary.failed));
+        }
+        if cancelled {
+            text.push_str(&format!("; cancelled with {} not processed", summary.remaining));
+        }
+        if summary.failed > 0 {
+            Status::Error(text)
+        } else {
+            Status::Info(text)
+        }
+    }
+}
+
+// The compression settings offered, from fastest to smallest for each algorithm.
+const COMPRESSION_LEVELS: [Compression; 8] = [
+    Compression::None,
+    Compression::Zstd(1),
+    Compression::Zstd(DEFAULT_ZSTD_LEVEL),
+    Compression::Zstd(9),
+    Compression::Zstd(19),
+    Compression::Deflate(1),
+    Compression::Deflate(DEFAULT_DEFLATE_LEVEL),
+    Compression::Deflate(9),
+];
+
+// Colour of error messages, in the message line and in the file list.
+const ERROR_COLOR: Color = Color::from_rgb(0.8, 0.1, 0.1);
+
+// Colour of the password strength estimate, from red for weak passwords to green.
+fn strength_color(strength: Strength) -> Color {
+    match strength {
+        Strength::VeryWeak | Strength::Weak => ERROR_COLOR,
+        Strength::Fair => Color::from_rgb(0.8, 0.5, 0.0),
+        Strength::Strong | Strength::VeryStrong => Color::from_rgb(0.1, 0.6, 0.2),
+    }
+}
+
+//DropZone is the style of the file list, which is where files can be dropped: a thin
+// border normally, and a highlighted one while files are dragged over the window.
+struct DropZone {
+    hovered: bool,
+}
+
+impl container::StyleSheet for DropZone {
+    fn style(&self) -> container::Style {
+        if self.hovered {
+            container::Style {
+                background: Some(Background::Color(Color::from_rgb(0.85, 0.92, 1.0))),
+                border_width: 2.0,
+                border_color: Color::from_rgb(0.2, 0.5, 0.9),
+                border_radius: 4.0,
+                ..container::Style::default()
+            }
+        } else {
+            container::Style {
+                border_width: 1.0,
+                border_color: Color::from_rgb(0.7, 0.7, 0.7),
+                border_radius: 4.0,
+                ..container::Style::default()
+            }
+        }
+    }
+}
+
+//This function describes how a file was encrypted, from its header.
+fn describe(header: &Header) -> String {
+    let slots: Vec<_> = header.slots.iter().map(KeySlot::to_string).collect();
+    let mut text = format!("Encrypted with {}, opened with: {}", header.cipher, slots.join("; or "));
+    if let Some(size) = header.file_size {
+        text.push_str(&format!(" ({})", format_bytes(size)));
+    }
+    if header.compression != Compression::None {
+        text.push_str(&format!("; compressed with {}", header.compression));
+    }
+    if header.metadata.is_some() {
+        text.push_str("; details of the original encrypted");
     }
-    key
+    text
 }
+
+//This function lists what the header of a file says about it, as label and value, for the
+// details of a selected file. None of it needs the password, apart from the original name,
+// which is sealed into the `metadata`.
+fn header_details(header: &Header, metadata: Option<&Metadata>) -> Vec<(&'static str, String)> {
+    let kdfs: Vec<_> = header
+        .slots
+        .iter()
+        .filter_map(|slot| match slot {
+            KeySlot::Password { kdf, .. } => Some(kdf.to_string()),
+            KeySlot::Recipient(_) => None,
+        })
+        .collect();
+    let key_derivation = if kdfs.is_empty() { String::from("none, public keys only") } else { kdfs.join("; ") };
+    let name_label = if header.archive { "Original folder" } else { "Original name" };
+    let name = match metadata {
+        Some(metadata) => metadata.name.clone().unwrap_or_else(|| String::from("not stored")),
+        None if header.metadata.is_some() => String::from("encrypted"),
+        None => String::from("not stored"),
+    };
+    vec![
+        ("Format version", CURRENT_VERSION.to_string()),
+        ("Cipher", header.cipher.to_string()),
+        ("Key derivation", key_derivation),
+        ("Key slots", header.slots.len().to_string()),
+        ("Encrypted on", header.created.map_or_else(|| String::from("not recorded"), metadata::format_time)),
+        ("Chunk size", format_bytes(header.chunk_size.into())),
+        (name_label, name),
+        ("Original size", header.file_size.map_or_else(|| String::from("not recorded"), |size| format!("{} ({} bytes)", format_bytes(size), size))),
+        ("Compression", header.compression.to_string()),
+    ]
+}
+
+//This function lists what the encrypted metadata of a file says, like header_details.
+fn metadata_details(metadata: &Metadata) -> Vec<(&'static str, String)> {
+    vec![
+        ("Last modified", metadata.modified.map_or_else(|| String::from("not recorded"), metadata::format_time)),
+        ("Permissions", metadata.permissions.map_or_else(|| String::from("not recorded"), format_permissions)),
+    ]
+}
+
+//This function shows Unix permission bits the way ls does, e.g. "rw-r----- (640)".
+fn format_permissions(mode: u32) -> String {
+    let mut text = String::new();
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        text.push(if bits & 4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    format!("{} ({:o})", text, mode & 0o777)
+}
+
+//This function shortens a public key for display, e.g. "age1qyq...3y6tqg".
+fn abbreviate(key: &str) -> String {
+    if key.len() <= 16 {
+        return key.to_string();
+    }
+    format!("{}...{}", &key[..7], &key[key.len() - 6..])
+}
+
+//This function formats a byte count for display, e.g. 1536 as "1.5 KiB".
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
  //The main() function is the entry point for the program. It calls the App::run() function
  // with the default settings from the Settings module. The App::run() function is responsible
  // for setting up the program's environment, launching the application, and handling any
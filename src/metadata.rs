@@ -0,0 +1,184 @@
+/*
+Facts about the original file that only those who can decrypt it get to see: its name,
+when it was last modified and, on Unix, its permission bits. They are sealed into a field
+of the header (see container.rs) with ChaCha20-Poly1305, under a key derived from the
+payload key with HKDF-SHA256, so anyone who can open the file can read them without
+decrypting the payload. Decrypting names the output after the original once the key has
+been found and restores the time and permissions on it. The size of the original is not
+among them: the header keeps it in the clear.
+
+The sealed metadata is a sequence of fields, each a tag (1 byte), a length (2 bytes) and
+a value: the name (UTF-8), the modification time (seconds since 1970 as u64 and
+nanoseconds as u32; times before 1970 are not recorded) and the permissions (u32), all
+little-endian. Encrypted folders record only their name.
+ */
+use crate::container::FormatError;
+use crate::error::Error;
+use crate::kdf::KEY_LEN;
+use crate::keyslot::{self, WrapCipher};
+use crate::secret::Key;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::fs::{self, File};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// HKDF info string, which keeps the metadata key apart from the payload key it comes from.
+const METADATA_INFO: &[u8] = b"A256CRYP metadata";
+
+const TAG_MODIFIED: u8 = 2;
+const TAG_PERMISSIONS: u8 = 3;
+const TAG_NAME: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+//Metadata is what is recorded about the original file. `name` is None when the name was
+// left out (see EncryptOptions), and `permissions` holds the Unix rwx bits and is None
+// for files encrypted elsewhere.
+pub struct Metadata {
+    pub name: Option<String>,
+    pub modified: Option<SystemTime>,
+    pub permissions: Option<u32>,
+}
+
+impl Metadata {
+    //Records the metadata of a file being encrypted under `name`.
+    pub fn from_file(name: Option<String>, metadata: &fs::Metadata) -> Metadata {
+        let modified = metadata.modified().ok().filter(|m| *m >= UNIX_EPOCH);
+        Metadata { name, modified, permissions: permissions(metadata) }
+    }
+
+    //Gives a decrypted file the time and permissions recorded. Permissions are left as
+    // they are on systems without them.
+    pub(crate) fn apply(&self, file: &File) -> std::io::Result<()> {
+        if let Some(modified) = self.modified {
+            file.set_modified(modified)?;
+        }
+        if let Some(mode) = self.permissions {
+            set_permissions(file, mode)?;
+        }
+        Ok(())
+    }
+
+    //Seals the metadata under a key derived from the payload key. Names too long for a
+    // field are left out.
+    pub fn seal(&self, payload_key: &[u8; KEY_LEN]) -> Vec<u8> {
+        let mut fields = Vec::new();
+        if let Some(name) = self.name.as_deref().filter(|name| name.len() <= usize::from(u16::MAX)) {
+            push_field(&mut fields, TAG_NAME, name.as_bytes());
+        }
+        if let Some(since) = self.modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok()) {
+            let value = [&since.as_secs().to_le_bytes()[..], &since.subsec_nanos().to_le_bytes()].concat();
+            push_field(&mut fields, TAG_MODIFIED, &value);
+        }
+        if let Some(mode) = self.permissions {
+            push_field(&mut fields, TAG_PERMISSIONS, &mode.to_le_bytes());
+        }
+        cipher(payload_key).seal(&fields)
+    }
+
+    //Opens what seal produced. The sealed bytes are authenticated with the payload as part
+    // of the header, so a failure here means the file was written wrongly, not modified.
+    pub fn open(sealed: &[u8], payload_key: &[u8; KEY_LEN]) -> Result<Metadata, Error> {
+        let invalid = |reason: &str| Error::from(FormatError::Invalid(format!("encrypted metadata: {}", reason)));
+        let fields = cipher(payload_key).open(sealed).map_err(|_| invalid("cannot be decrypted"))?;
+
+        let mut name = None;
+        let mut modified = None;
+        let mut permissions = None;
+        let mut rest = fields.as_slice();
+        while let [tag, len_low, len_high, tail @ ..] = rest {
+            let len = usize::from(u16::from_le_bytes([*len_low, *len_high]));
+            if tail.len() < len {
+                return Err(invalid("truncated field"));
+            }
+            let (value, tail) = tail.split_at(len);
+            rest = tail;
+            match (*tag, value.len()) {
+                (TAG_NAME, _) => {
+                    name = Some(String::from_utf8(value.to_vec()).map_err(|_| invalid("the name is not valid UTF-8"))?);
+                }
+                (TAG_MODIFIED, 12) => {
+                    let seconds = u64::from_le_bytes(value[..8].try_into().expect("8 bytes"));
+                    let nanos = u32::from_le_bytes(value[8..].try_into().expect("4 bytes"));
+                    if nanos >= 1_000_000_000 {
+                        return Err(invalid("invalid modification time"));
+                    }
+                    modified = UNIX_EPOCH.checked_add(Duration::new(seconds, nanos));
+                }
+                (TAG_PERMISSIONS, 4) => permissions = Some(u32::from_le_bytes(value.try_into().expect("4 bytes")) & 0o777),
+                (TAG_MODIFIED | TAG_PERMISSIONS, _) => return Err(invalid("field of the wrong length")),
+                (other, _) => return Err(invalid(&format!("unknown field {}", other))),
+            }
+        }
+        if !rest.is_empty() {
+            return Err(invalid("truncated field"));
+        }
+        Ok(Metadata { name, modified, permissions })
+    }
+}
+
+//Formats a time recorded in a file as UTC, e.g. "2024-03-01 14:05:09 UTC". Times before
+// 1970 are shown as 1970.
+pub fn format_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // The days since 1970 as a proleptic Gregorian date, counted in 400 year eras that
+    // start on March 1st, so leap days fall at the end of a year.
+    let shifted = days + 719_468;
+    let (era, day_of_era) = (shifted / 146_097, shifted % 146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60
+    )
+}
+
+fn push_field(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+// Every file has its own payload key, so each metadata key seals exactly once.
+fn cipher(payload_key: &[u8; KEY_LEN]) -> WrapCipher {
+    let mut key = Key::empty();
+    Hkdf::<Sha256>::new(None, payload_key)
+        .expand(METADATA_INFO, key.as_mut_bytes())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    keyslot::wrap_cipher(&key)
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn permissions(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_permissions(file: &File, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_file: &File, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
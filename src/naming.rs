@@ -0,0 +1,99 @@
+/*
+Where output files go when the user has not picked a path. Encrypting appends an
+extension to the whole file name (report.pdf becomes report.pdf.aes), and decrypting
+restores the name the file had when it was encrypted, which is sealed with the key and
+so only known once the file has been opened (see files::decrypt_file_named). Both can be
+redirected to another folder.
+ */
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+//Extension given to encrypted files unless another one is configured.
+pub const DEFAULT_EXTENSION: &str = "aes";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+//OutputNaming holds the naming settings: the extension for encrypted files, and the
+// folder outputs are written to (None means next to the input).
+pub struct OutputNaming {
+    pub extension: String,
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for OutputNaming {
+    fn default() -> Self {
+        OutputNaming { extension: String::from(DEFAULT_EXTENSION), directory: None }
+    }
+}
+
+impl OutputNaming {
+    //Returns the path encrypting `input` writes to.
+    pub fn encrypted_path(&self, input: &Path) -> PathBuf {
+        let mut name = input.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(self.extension());
+        self.directory_for(input).join(name)
+    }
+
+    //Returns the path decrypting `input` writes to, given the original name recorded in
+    // it. Without one (or with one that is not a plain file name), the extension is taken
+    // off the encrypted name instead, or if it has none, ".decrypted" is added.
+    pub fn decrypted_path(&self, input: &Path, original_name: Option<&str>) -> PathBuf {
+        let name = match original_name.filter(|name| is_plain_file_name(name)) {
+            Some(name) => PathBuf::from(name),
+            None => {
+                let name = input.file_name().unwrap_or_default();
+                let extension = input.extension().and_then(|e| e.to_str());
+                if extension.is_some_and(|e| e.eq_ignore_ascii_case(self.extension())) {
+                    PathBuf::from(input.file_stem().unwrap_or(name))
+                } else {
+                    let mut name = name.to_os_string();
+                    name.push(".decrypted");
+                    PathBuf::from(name)
+                }
+            }
+        };
+        self.directory_for(input).join(name)
+    }
+
+    // The configured extension without a leading dot, or the default if none is set.
+    fn extension(&self) -> &str {
+        match self.extension.trim().trim_start_matches('.') {
+            "" => DEFAULT_EXTENSION,
+            extension => extension,
+        }
+    }
+
+    fn directory_for(&self, input: &Path) -> PathBuf {
+        match &self.directory {
+            Some(directory) => directory.clone(),
+            None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+        }
+    }
+}
+
+// The original name comes from the file being decrypted, so it is not trusted to
+// stay inside the output folder: anything that is not a single, ordinary file name
+// (a path, "." or "..", or a Windows drive prefix such as "C:evil") is ignored.
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    let single = matches!((components.next(), components.next()), (Some(Component::Normal(_)), None));
+    single && !name.contains(['/', '\\', ':', '\0'])
+}
+
+//Returns `path` if nothing exists there, otherwise the first of "name (1).ext",
+// "name (2).ext", ... that is free. Used to keep both files when an output exists.
+pub fn unused_path(path: &Path) -> PathBuf {
+    let mut candidate = path.to_path_buf();
+    let mut n = 0;
+    while fs::symlink_metadata(&candidate).is_ok() {
+        n += 1;
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!(" ({})", n));
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        candidate = path.with_file_name(name);
+    }
+    candidate
+}
@@ -0,0 +1,194 @@
+/*
+Public-key encryption, so files can be sent to colleagues without agreeing on a password
+first. Everyone who receives files has an X25519 key pair: the public half (a recipient,
+"age1...") is handed out, the secret half (an identity, "AGE-SECRET-KEY-1...") stays with
+its owner. Keys are written the way age writes them, so keys made by age-keygen work here
+and the other way round; the encrypted files are in this app's format, not age's.
+
+A file encrypted to recipients gets a random file key instead of one derived from a
+password, and the file key is wrapped for every recipient the way age's X25519 recipients
+do it: an ephemeral key pair is generated, the secret it shares with the recipient is run
+through HKDF-SHA256 (salted with both public keys), and the file key is sealed with
+ChaCha20-Poly1305 under the result. The ephemeral public key and the sealed file key make
+up the recipient's stanza, which is kept in a key slot of the header (see keyslot.rs).
+ */
+use crate::container::{Stanza, PUBLIC_KEY_LEN};
+use crate::error::Error;
+use crate::keyfile;
+use crate::keyslot::wrap_cipher;
+use crate::secret::{Key, Zeroizing};
+use bech32::{FromBase32, ToBase32, Variant};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+// Human-readable prefixes of the bech32 encodings, as used by age.
+const RECIPIENT_HRP: &str = "age";
+const IDENTITY_HRP: &str = "age-secret-key-";
+
+// HKDF info string, which keeps the wrapping key apart from keys derived for other uses.
+const WRAP_INFO: &[u8] = b"A256CRYP X25519 file key";
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//Recipient is a public key files can be encrypted to. It is parsed from and displayed as
+// "age1..." text.
+pub struct Recipient(PublicKey);
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = bech32::encode(RECIPIENT_HRP, self.0.as_bytes().to_base32(), Variant::Bech32).map_err(|_| fmt::Error)?;
+        f.write_str(&text)
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let bytes = decode(text.trim(), RECIPIENT_HRP, "public key")?;
+        Ok(Recipient(PublicKey::from(*bytes)))
+    }
+}
+
+#[derive(Clone)]
+//Identity is the secret key of a recipient, which decrypts the files encrypted to it. It
+// is wiped from memory when dropped, and only its public key is ever shown.
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    pub fn generate() -> Identity {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    //Returns the public key that files for this identity are encrypted to.
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    //Returns the identity as "AGE-SECRET-KEY-1..." text, to be kept secret.
+    pub fn to_secret_string(&self) -> Zeroizing<String> {
+        let bytes = Zeroizing::new(self.0.to_bytes());
+        let text = Zeroizing::new(
+            bech32::encode(IDENTITY_HRP, bytes.to_base32(), Variant::Bech32).expect("the prefix is valid bech32"),
+        );
+        Zeroizing::new(text.to_uppercase())
+    }
+}
+
+impl FromStr for Identity {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let bytes = decode(text.trim(), IDENTITY_HRP, "secret key")?;
+        Ok(Identity(StaticSecret::from(*bytes)))
+    }
+}
+
+// Identities are told apart by their public keys, which is also all Debug shows.
+impl PartialEq for Identity {
+    fn eq(&self, other: &Self) -> bool {
+        self.recipient() == other.recipient()
+    }
+}
+
+impl Eq for Identity {}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.recipient())
+    }
+}
+
+//Reads the identities in an identity file, as written by write_identity or age-keygen:
+// one key per line, with blank lines and lines starting with # ignored.
+pub fn read_identities(path: &Path) -> Result<Vec<Identity>, Error> {
+    let text = Zeroizing::new(fs::read_to_string(path).map_err(|e| Error::with_path(e, path))?);
+    let identities = key_lines(&text).map(Identity::from_str).collect::<Result<Vec<_>, _>>()?;
+    if identities.is_empty() {
+        return Err(Error::InvalidKey(format!("{} holds no secret keys", path.display())));
+    }
+    Ok(identities)
+}
+
+//Reads the recipients in a file of public keys, one per line, in the same layout as
+// an identity file.
+pub fn read_recipients(path: &Path) -> Result<Vec<Recipient>, Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::with_path(e, path))?;
+    let recipients = key_lines(&text).map(Recipient::from_str).collect::<Result<Vec<_>, _>>()?;
+    if recipients.is_empty() {
+        return Err(Error::InvalidKey(format!("{} holds no public keys", path.display())));
+    }
+    Ok(recipients)
+}
+
+//Writes `identity` to a new identity file, with its public key in a comment the way
+// age-keygen does. An existing file is never replaced, and on Unix the file is only
+// readable by its owner.
+pub fn write_identity(path: &Path, identity: &Identity) -> Result<(), Error> {
+    let mut file = keyfile::create_private(path)?;
+    writeln!(file, "# public key: {}", identity.recipient())?;
+    writeln!(file, "{}", identity.to_secret_string().as_str())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn key_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn decode(text: &str, hrp: &str, what: &str) -> Result<Zeroizing<[u8; PUBLIC_KEY_LEN]>, Error> {
+    let invalid = || Error::InvalidKey(format!("not an age {}", what));
+    let (found, data, variant) = bech32::decode(text).map_err(|_| invalid())?;
+    if found != hrp || variant != Variant::Bech32 {
+        return Err(invalid());
+    }
+    let bytes = Zeroizing::new(Vec::<u8>::from_base32(&data).map_err(|_| invalid())?);
+    let key: [u8; PUBLIC_KEY_LEN] = bytes.as_slice().try_into().map_err(|_| invalid())?;
+    Ok(Zeroizing::new(key))
+}
+
+//Wraps the file key for `recipient`. Fails for the few public keys that would make the
+// shared secret predictable, which no real key pair has.
+pub(crate) fn wrap(file_key: &Key, recipient: &Recipient) -> Result<Stanza, Error> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(&recipient.0);
+    if !shared.was_contributory() {
+        return Err(Error::InvalidKey(format!("{} cannot be encrypted to", recipient)));
+    }
+    let wrap_key = wrap_key(shared.as_bytes(), &ephemeral_public, &recipient.0);
+    Ok(Stanza { ephemeral: ephemeral_public.to_bytes(), wrapped: wrap_cipher(&wrap_key).seal(file_key.as_bytes()) })
+}
+
+//Unwraps the file key from the stanza with the first of the identities that opens it, or
+// returns None if none of them is the stanza's recipient.
+pub(crate) fn unwrap(stanza: &Stanza, identities: &[Identity]) -> Option<Key> {
+    let ephemeral = PublicKey::from(stanza.ephemeral);
+    for identity in identities {
+        let shared = identity.0.diffie_hellman(&ephemeral);
+        if !shared.was_contributory() {
+            continue;
+        }
+        let wrap_key = wrap_key(shared.as_bytes(), &ephemeral, &PublicKey::from(&identity.0));
+        if let Some(key) = wrap_cipher(&wrap_key).open_key(&stanza.wrapped) {
+            return Some(key);
+        }
+    }
+    None
+}
+
+fn wrap_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Key {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes().as_slice()].concat();
+    let mut key = Key::empty();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, key.as_mut_bytes())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
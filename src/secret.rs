@@ -0,0 +1,87 @@
+/*
+Keeping secrets in memory no longer than they are needed. Derived keys, the bytes a key is
+derived from and decrypted data are overwritten with zeros when they are dropped (with the
+zeroize crate, whose writes the compiler cannot optimise away), and keys are locked into
+RAM where the system allows it, so they are never written to swap. This is best effort:
+the operating system, the GUI toolkit and the cipher implementations may still keep copies
+we cannot reach, and a String that grew while a password was typed leaves its earlier
+buffers behind. It narrows what can be recovered from a shared machine's memory or swap,
+it does not make that impossible.
+ */
+use crate::kdf::KEY_LEN;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fmt;
+use zeroize::Zeroize;
+
+pub use zeroize::Zeroizing;
+
+//Key is a 256-bit key, derived or random. It is kept on the heap so moving it never
+// leaves a copy behind, its page is locked into memory while it exists, and it is zeroed
+// when dropped.
+pub struct Key(Box<[u8; KEY_LEN]>);
+
+impl Key {
+    //Returns an all-zero key for the KDF to fill in.
+    pub(crate) fn empty() -> Key {
+        let key = Key(Box::new([0u8; KEY_LEN]));
+        lock(key.0.as_ptr(), KEY_LEN);
+        key
+    }
+
+    //Returns a new random key, for a file key that is wrapped rather than derived.
+    pub(crate) fn random() -> Key {
+        let mut key = Key::empty();
+        OsRng.fill_bytes(key.as_mut_bytes());
+        key
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+
+    pub(crate) fn as_mut_bytes(&mut self) -> &mut [u8; KEY_LEN] {
+        &mut self.0
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        unlock(self.0.as_ptr(), KEY_LEN);
+    }
+}
+
+// Never prints the key itself.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+// Locking can fail, most often because the process is over its limit of locked memory
+// (RLIMIT_MEMLOCK); the key is then used unlocked rather than not at all. Locks do not
+// nest, so unlocking one key also unlocks any other key that shares its page; keys only
+// live for the duration of one file, so that window is short.
+#[cfg(unix)]
+fn lock(ptr: *const u8, len: usize) {
+    // SAFETY: the range is a live allocation owned by the Key; mlock only changes how the
+    // pages holding it are paged and never touches their contents.
+    unsafe {
+        libc::mlock(ptr.cast(), len);
+    }
+}
+
+#[cfg(unix)]
+fn unlock(ptr: *const u8, len: usize) {
+    // SAFETY: as for lock; the allocation is still live while the Key is being dropped.
+    unsafe {
+        libc::munlock(ptr.cast(), len);
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_ptr: *const u8, _len: usize) {}
+
+#[cfg(not(unix))]
+fn unlock(_ptr: *const u8, _len: usize) {}
@@ -0,0 +1,194 @@
+/*
+Streaming encryption, so files of any size can be processed with a small, fixed amount
+of memory. The plaintext is cut into chunks of `chunk_size` bytes and every chunk is
+sealed separately with the file's AEAD, following the STREAM construction (Hoang,
+Reyhanitabar, Rogaway, Vizár): the 12 byte nonce of each chunk is
+
+    prefix (7 random bytes from the header) || chunk counter (u32 BE) || last flag (1 byte)
+
+The counter stops chunks from being reordered or dropped, and the flag, which is 1 only
+on the final chunk, stops the file from being cut short at a chunk boundary. Every chunk
+except the last holds exactly `chunk_size` bytes of plaintext; the last one holds fewer
+(possibly none), which is how the reader recognises it.
+ */
+use crate::cipher::{AeadCipher, AuthenticationError, TAG_LEN};
+use crate::container::Cipher;
+use crate::kdf::KEY_LEN;
+use crate::secret::Zeroizing;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::io::{self, Read, Write};
+
+//Chunk size used for new files.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+//Largest chunk size accepted from a file header, which bounds the memory a file can
+// make the reader allocate.
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+
+//Length of the random nonce prefix stored in the header of every file.
+pub const NONCE_PREFIX_LEN: usize = 7;
+
+//Draws the random nonce prefix for a new file.
+pub fn generate_nonce_prefix() -> [u8; NONCE_PREFIX_LEN] {
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    OsRng.fill_bytes(&mut prefix);
+    prefix
+}
+
+//StreamWriter encrypts everything written to it and passes the sealed chunks on to the
+// inner writer. finish() must be called once all data has been written, otherwise the
+// final chunk is missing and the file will not decrypt. The plaintext it buffers is wiped
+// when it is dropped.
+pub struct StreamWriter<W: Write> {
+    inner: W,
+    aead: AeadCipher,
+    nonce_prefix: Vec<u8>,
+    aad: Vec<u8>,
+    counter: u32,
+    chunk_size: usize,
+    buffer: Zeroizing<Vec<u8>>,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(inner: W, cipher: Cipher, key: &[u8; KEY_LEN], nonce_prefix: &[u8], chunk_size: u32, aad: Vec<u8>) -> Self {
+        StreamWriter {
+            inner,
+            aead: AeadCipher::new(cipher, key),
+            nonce_prefix: nonce_prefix.to_vec(),
+            aad,
+            counter: 0,
+            chunk_size: chunk_size as usize,
+            buffer: Zeroizing::new(Vec::with_capacity(chunk_size as usize)),
+        }
+    }
+
+    //Seals whatever is buffered as the final chunk and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal_chunk(true)?;
+        Ok(self.inner)
+    }
+
+    fn seal_chunk(&mut self, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(&self.nonce_prefix, self.counter, last);
+        let sealed = self.aead.seal(&nonce, &self.aad, &self.buffer);
+        self.inner.write_all(&sealed)?;
+        self.buffer.clear();
+        self.counter = next_counter(self.counter)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for StreamWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let take = data.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..take]);
+        if self.buffer.len() == self.chunk_size {
+            self.seal_chunk(false)?;
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+//StreamReader reads sealed chunks from the inner reader and yields the verified
+// plaintext. A chunk that fails to authenticate, or a stream that ends without its
+// final chunk, is reported as an InvalidData error wrapping AuthenticationError.
+// Plaintext is only handed out after the chunk it belongs to has been verified, and is
+// wiped once the next chunk replaces it.
+pub struct StreamReader<R: Read> {
+    inner: R,
+    aead: AeadCipher,
+    nonce_prefix: Vec<u8>,
+    aad: Vec<u8>,
+    counter: u32,
+    chunk: Vec<u8>,
+    plaintext: Zeroizing<Vec<u8>>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(inner: R, cipher: Cipher, key: &[u8; KEY_LEN], nonce_prefix: &[u8], chunk_size: u32, aad: Vec<u8>) -> Self {
+        StreamReader {
+            inner,
+            aead: AeadCipher::new(cipher, key),
+            nonce_prefix: nonce_prefix.to_vec(),
+            aad,
+            counter: 0,
+            chunk: vec![0u8; chunk_size as usize + TAG_LEN],
+            plaintext: Zeroizing::new(Vec::new()),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    fn open_next_chunk(&mut self) -> io::Result<()> {
+        let filled = fill(&mut self.inner, &mut self.chunk)?;
+        if filled < TAG_LEN {
+            // The stream ended before the final chunk, so it was truncated.
+            return Err(authentication_failed());
+        }
+        let last = filled < self.chunk.len();
+        let nonce = chunk_nonce(&self.nonce_prefix, self.counter, last);
+        self.plaintext = self
+            .aead
+            .open(&nonce, &self.aad, &self.chunk[..filled])
+            .map(Zeroizing::new)
+            .map_err(|_| authentication_failed())?;
+        self.position = 0;
+        self.finished = last;
+        self.counter = next_counter(self.counter)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for StreamReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.open_next_chunk()?;
+        }
+        let n = out.len().min(self.plaintext.len() - self.position);
+        out[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+fn authentication_failed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, AuthenticationError)
+}
+
+fn chunk_nonce(prefix: &[u8], counter: u32, last: bool) -> Vec<u8> {
+    let mut nonce = Vec::with_capacity(NONCE_PREFIX_LEN + 5);
+    nonce.extend_from_slice(prefix);
+    nonce.extend_from_slice(&counter.to_be_bytes());
+    nonce.push(last as u8);
+    nonce
+}
+
+fn next_counter(counter: u32) -> io::Result<u32> {
+    counter
+        .checked_add(1)
+        .ok_or_else(|| io::Error::other("file is too large for the chunk counter"))
+}
+
+// Reads until the buffer is full or the reader is exhausted, returning how much was read.
+fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,102 @@
+/*
+A rough estimate of how hard a password is to guess, in the spirit of zxcvbn but much
+simpler. Every character is worth the bits needed to pick it from the character classes
+the password uses, except that characters an attacker's rules would try early (a repeat
+of the previous character, or the next one in a run like "abc" or "321") are worth
+about one bit, and well-known passwords are worth almost nothing whatever they contain.
+The result is only meant to steer people away from weak passwords, not to prove that a
+password is strong.
+ */
+use std::fmt;
+
+// A few of the most common passwords, compared without regard to case. Longer passwords
+// built from them are caught by the run and repeat rules well enough.
+const COMMON: &[&str] = &[
+    "password", "passw0rd", "p@ssw0rd", "123456", "12345678", "123456789", "1234567890",
+    "qwerty", "qwertyuiop", "azerty", "abc123", "111111", "123123", "letmein", "welcome",
+    "monkey", "dragon", "football", "baseball", "iloveyou", "admin", "login", "master",
+    "sunshine", "princess", "shadow", "superman", "trustno1", "secret", "changeme",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Strength {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+    VeryStrong,
+}
+
+impl fmt::Display for Strength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strength::VeryWeak => write!(f, "very weak"),
+            Strength::Weak => write!(f, "weak"),
+            Strength::Fair => write!(f, "fair"),
+            Strength::Strong => write!(f, "strong"),
+            Strength::VeryStrong => write!(f, "very strong"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+//Estimate is the guessing entropy of a password in bits, and the Strength it rates as.
+pub struct Estimate {
+    pub bits: f64,
+    pub strength: Strength,
+}
+
+//Estimates the strength of `password`.
+pub fn estimate(password: &str) -> Estimate {
+    let bits = if COMMON.iter().any(|common| common.eq_ignore_ascii_case(password)) {
+        // Among the first guesses of any attack.
+        (COMMON.len() as f64).log2()
+    } else {
+        entropy(password)
+    };
+    let strength = match bits {
+        b if b < 28.0 => Strength::VeryWeak,
+        b if b < 36.0 => Strength::Weak,
+        b if b < 60.0 => Strength::Fair,
+        b if b < 80.0 => Strength::Strong,
+        _ => Strength::VeryStrong,
+    };
+    Estimate { bits, strength }
+}
+
+fn entropy(password: &str) -> f64 {
+    let per_char = (pool_size(password) as f64).log2();
+    let mut bits = 0.0;
+    let mut previous: Option<char> = None;
+    for c in password.chars() {
+        let predictable = previous.is_some_and(|p| {
+            let step = c as i64 - p as i64;
+            step.abs() <= 1 && c.is_alphanumeric() == p.is_alphanumeric()
+        });
+        bits += if predictable { 1.0 } else { per_char };
+        previous = Some(c);
+    }
+    bits
+}
+
+// The number of characters an attacker has to consider for each position, given the
+// classes of characters that appear in the password.
+fn pool_size(password: &str) -> u32 {
+    let mut size = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        size += 10;
+    }
+    if password.chars().any(|c| c.is_ascii() && !c.is_ascii_alphanumeric()) {
+        size += 33;
+    }
+    if !password.is_ascii() {
+        size += 100;
+    }
+    size.max(1)
+}
@@ -0,0 +1,131 @@
+/*
+Removing the plaintext original once a file has been encrypted. Deleting a file only
+drops its name; the data stays on the disk until it happens to be reused. wipe therefore
+overwrites the contents with random bytes (which no file system can store compressed or
+leave out as zeros), flushes them to disk, and only then deletes the file. The file is
+opened once, with open, and the same handle can be read to check it before it is wiped.
+
+This is best effort and cannot be more. Solid-state drives and SD cards remap writes to
+fresh blocks and keep the old ones until the firmware erases them; copy-on-write and
+log-structured file systems (Btrfs, ZFS, APFS, F2FS) write the new bytes elsewhere too;
+snapshots, backups, cloud sync folders, swap and editors' temporary files keep copies of
+their own. On such systems the overwrite changes nothing an attacker with the raw disk
+cannot undo, and the only reliable protection is to keep plaintext on an encrypted disk
+in the first place.
+ */
+use crate::error::Error;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Size of the blocks of random bytes the file is overwritten with.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+//Overwrites the file at `path` with random bytes, flushes it to disk and deletes it.
+pub fn wipe_file(path: &Path) -> Result<(), Error> {
+    wipe(open(path)?, path)
+}
+
+//Opens the file at `path` for reading and wiping. Only regular files are wiped, and on
+// Unix only files with no other hard links, since overwriting would destroy the data
+// those other names still point to. The file is opened without following symbolic links
+// and checked through the open handle, so it cannot be swapped for a link or another
+// file between the check and the overwrite.
+pub fn open(path: &Path) -> Result<File, Error> {
+    let file = match open_no_follow(path) {
+        Ok(file) => file,
+        // Most likely a symbolic link, which is refused like anything else but a file.
+        Err(e) => {
+            return Err(match fs::symlink_metadata(path) {
+                Ok(metadata) if !metadata.is_file() => not_a_file(path),
+                _ => Error::with_path(e, path),
+            })
+        }
+    };
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(not_a_file(path));
+    }
+    if has_other_links(&metadata) {
+        return Err(Error::InvalidOptions(format!("{} has other hard links", path.display())));
+    }
+    Ok(file)
+}
+
+//Overwrites `file`, which open returned for `path`, from its start with random bytes,
+// flushes it to disk and deletes `path`.
+pub fn wipe(mut file: File, path: &Path) -> Result<(), Error> {
+    let metadata = file.metadata()?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut block = vec![0u8; BLOCK_SIZE];
+    let mut remaining = metadata.len();
+    while remaining > 0 {
+        let len = remaining.min(BLOCK_SIZE as u64) as usize;
+        OsRng.fill_bytes(&mut block[..len]);
+        file.write_all(&block[..len])?;
+        remaining -= len as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path).map_err(|e| Error::with_path(e, path))
+}
+
+//Tells whether two handles are open on the same file. Outside Unix this can only go by
+// the size and modification time.
+#[cfg(unix)]
+pub fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[cfg(not(unix))]
+pub fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+fn not_a_file(path: &Path) -> Error {
+    Error::InvalidOptions(format!("{} is not a regular file", path.display()))
+}
+
+// Opens `path` for reading and writing, failing on a symbolic link instead of following it. It is
+// opened non-blocking, so a FIFO without a reader fails rather than hangs; regular files
+// are not affected by that.
+#[cfg(unix)]
+fn open_no_follow(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK).open(path)
+}
+
+// FILE_FLAG_OPEN_REPARSE_POINT opens a symbolic link itself rather than its target.
+#[cfg(windows)]
+fn open_no_follow(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+    OpenOptions::new().read(true).write(true).custom_flags(FILE_FLAG_OPEN_REPARSE_POINT).open(path)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn open_no_follow(path: &Path) -> io::Result<File> {
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "symbolic link"));
+    }
+    OpenOptions::new().read(true).write(true).open(path)
+}
+
+#[cfg(unix)]
+fn has_other_links(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn has_other_links(_metadata: &fs::Metadata) -> bool {
+    false
+}
@@ -0,0 +1,195 @@
+/*
+Background processing for the GUI. A Job runs on its own thread so the window stays
+responsive while large files are encrypted, and reports back through an iced
+subscription: a Progress event every so often, then a single Done event with the
+outcome. Cancelling sets a shared flag that the file operation checks between reads;
+it then stops and removes its partial output.
+ */
+use crate::{IfExists, Mode};
+use aes256_encryption_gui_app::container::Header;
+use aes256_encryption_gui_app::naming::{self, OutputNaming};
+use aes256_encryption_gui_app::recipient::{Identity, Recipient};
+use aes256_encryption_gui_app::secret::Zeroizing;
+use aes256_encryption_gui_app::{archive, files, Compression, DecryptOptions, EncryptOptions, Error};
+use iced::futures::channel::mpsc;
+use iced::futures::{future, StreamExt};
+use iced::Subscription;
+use iced_native::subscription;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often progress is reported to the UI; more often would only cost redraws.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+//Job describes one file or folder to process. The id tells iced subscriptions apart, so every
+// new job must get a new id; the cancel flag is shared with the App. An existing output
+// file is only replaced if `overwrite` is set. The keyfiles are combined with the password,
+// which is wiped from memory when the last clone of the job is dropped. A file is also
+// encrypted to the recipients, and the identities are tried when decrypting. With
+// remove_original the input is wiped once it has been encrypted and checked. New files
+// are compressed as `compression` says, and record their names if `store_name` is set.
+// Without an `output`, a decrypted file is named by `naming` after the original once the
+// file has been opened, and an existing output is dealt with as `if_exists` says.
+// Verifying writes nothing, so it has no output.
+pub struct Job {
+    pub id: u64,
+    pub mode: Mode,
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub naming: OutputNaming,
+    pub if_exists: IfExists,
+    pub overwrite: bool,
+    pub password: Zeroizing<String>,
+    pub keyfiles: Vec<PathBuf>,
+    pub recipients: Vec<Recipient>,
+    pub identities: Vec<Identity>,
+    pub remove_original: bool,
+    pub compression: Compression,
+    pub store_name: bool,
+    pub cancel: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone)]
+//Event is what a running job reports back to the App.
+pub enum Event {
+    Progress { processed: u64, total: u64 },
+    Done(Outcome),
+}
+
+#[derive(Debug, Clone)]
+//Outcome is how a job ended: with the path of the file it wrote, with the header of the
+// file it verified, skipped or waiting for the user because the output it named exists,
+// because the user cancelled it, or with an error.
+pub enum Outcome {
+    Finished(PathBuf),
+    Verified(Header),
+    Skipped,
+    Conflict(PathBuf),
+    Cancelled,
+    Failed(Error),
+}
+
+enum State {
+    Ready(Box<Job>),
+    Running(mpsc::UnboundedReceiver<Event>),
+    Done,
+}
+
+//Returns the subscription that runs the job. iced starts it the first time it sees the
+// job's id and drops it (leaving the thread to finish on its own) once the App stops
+// returning it.
+pub fn subscription(job: &Job) -> Subscription<Event> {
+    subscription::unfold(job.id, State::Ready(Box::new(job.clone())), |state| async move {
+        match state {
+            State::Ready(job) => {
+                let (sender, receiver) = mpsc::unbounded();
+                thread::spawn(move || run(*job, sender));
+                (None, State::Running(receiver))
+            }
+            State::Running(mut receiver) => match receiver.next().await {
+                Some(Event::Done(outcome)) => (Some(Event::Done(outcome)), State::Done),
+                Some(progress) => (Some(progress), State::Running(receiver)),
+                None => (None, State::Done),
+            },
+            State::Done => future::pending().await,
+        }
+    })
+}
+
+fn run(job: Job, sender: mpsc::UnboundedSender<Event>) {
+    let folder = match job.mode {
+        Mode::Encrypt => job.input.is_dir(),
+        Mode::Decrypt => files::read_header(&job.input).is_ok_and(|header| header.archive),
+        // Verifying reads an encrypted folder like any other file.
+        Mode::Verify => false,
+    };
+    let total = match job.mode {
+        Mode::Encrypt if folder => archive::folder_size(&job.input),
+        _ => fs::metadata(&job.input).map(|m| m.len()).unwrap_or(0),
+    };
+    let mut last_report = Instant::now();
+    let mut progress = |processed: u64| {
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            last_report = Instant::now();
+            let _ = sender.unbounded_send(Event::Progress { processed, total });
+        }
+        !job.cancel.load(Ordering::Relaxed)
+    };
+
+    let result = match job.mode {
+        Mode::Encrypt => {
+            let options = EncryptOptions {
+                overwrite: job.overwrite,
+                keyfiles: job.keyfiles.clone(),
+                recipients: job.recipients.clone(),
+                remove_original: job.remove_original,
+                compression: job.compression,
+                store_name: job.store_name,
+                ..EncryptOptions::default()
+            };
+            let output = job.output.clone().unwrap_or_else(|| job.naming.encrypted_path(&job.input));
+            let encrypted = if folder {
+                archive::encrypt_folder(&job.input, &output, &job.password, &options, &mut progress)
+            } else {
+                files::encrypt_file(&job.input, &output, &job.password, &options, &mut progress)
+            };
+            encrypted.map(|()| Outcome::Finished(output))
+        }
+        Mode::Decrypt => {
+            let options = DecryptOptions {
+                overwrite: job.overwrite,
+                keyfiles: job.keyfiles.clone(),
+                identities: job.identities.clone(),
+                ..DecryptOptions::default()
+            };
+            let mut output_for = |name: Option<&str>| decrypted_output(&job, name);
+            let decrypted = if folder {
+                archive::decrypt_folder_named(&job.input, &job.password, &options, &mut output_for, &mut progress)
+            } else {
+                files::decrypt_file_named(&job.input, &job.password, &options, &mut output_for, &mut progress)
+            };
+            match decrypted {
+                Err(Error::AlreadyExists(_)) if job.output.is_none() && job.if_exists == IfExists::Skip => Ok(Outcome::Skipped),
+                Err(Error::AlreadyExists(output)) if job.output.is_none() => Ok(Outcome::Conflict(output)),
+                decrypted => decrypted.map(Outcome::Finished),
+            }
+        }
+        Mode::Verify => {
+            let options = DecryptOptions {
+                keyfiles: job.keyfiles.clone(),
+                identities: job.identities.clone(),
+                ..DecryptOptions::default()
+            };
+            files::verify_file(&job.input, &job.password, &options, &mut progress).map(Outcome::Verified)
+        }
+    };
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(Error::Cancelled) => Outcome::Cancelled,
+        Err(e) => Outcome::Failed(e),
+    };
+    let _ = sender.unbounded_send(Event::Done(outcome));
+}
+
+// Returns the path a decrypted file is written to: the one chosen for the job, or else
+// one named after the original. An existing output is replaced or kept as `if_exists`
+// says; otherwise Error::AlreadyExists stops the job before anything is written.
+fn decrypted_output(job: &Job, original_name: Option<&str>) -> Result<PathBuf, Error> {
+    if let Some(output) = &job.output {
+        return Ok(output.clone());
+    }
+    let output = job.naming.decrypted_path(&job.input, original_name);
+    if fs::symlink_metadata(&output).is_err() {
+        return Ok(output);
+    }
+    match job.if_exists {
+        IfExists::Overwrite => Ok(output),
+        IfExists::Rename => Ok(naming::unused_path(&output)),
+        IfExists::Skip | IfExists::Ask => Err(Error::AlreadyExists(output)),
+    }
+}
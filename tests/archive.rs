@@ -0,0 +1,130 @@
+/*
+Encrypted folders: a folder comes back with its files, subfolders and modification
+times, a modified archive leaves nothing behind, and paths that would lead out of the
+output folder are refused.
+ */
+mod common;
+use common::{temp_dir, KDF, PASSWORD};
+
+use aes256_encryption_gui_app::archive::{decrypt_folder, decrypt_folder_named, encrypt_folder};
+use aes256_encryption_gui_app::container::Header;
+use aes256_encryption_gui_app::stream::{self, StreamWriter};
+use aes256_encryption_gui_app::{decrypt_file, files, verify_file, kdf, keyslot, Cipher, Compression, DecryptOptions, EncryptOptions, Error, OutputNaming};
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn decrypt(input: &Path, output: &Path) -> Result<(), Error> {
+    decrypt_folder(input, output, PASSWORD, &DecryptOptions::default(), &mut |_| true)
+}
+
+// Writes `tar` as the payload of an encrypted folder, the way a malicious sender could.
+fn encrypt_tar(path: &Path, tar: &[u8]) {
+    let key = KDF.derive_key(PASSWORD.as_bytes(), &kdf::generate_salt());
+    let header = Header {
+        cipher: Cipher::Aes256Gcm,
+        slots: vec![keyslot::password_slot(&key, PASSWORD, &[], KDF).unwrap()],
+        nonce: stream::generate_nonce_prefix().to_vec(),
+        chunk_size: stream::DEFAULT_CHUNK_SIZE,
+        file_size: None,
+        archive: true,
+        compression: Compression::None,
+        metadata: None,
+        created: None,
+    };
+    let mut file = File::create(path).unwrap();
+    header.write_to(&mut file).unwrap();
+    let aad = header.authenticated_bytes().unwrap();
+    let mut writer = StreamWriter::new(file, header.cipher, key.as_bytes(), &header.nonce, stream::DEFAULT_CHUNK_SIZE, aad);
+    std::io::Write::write_all(&mut writer, tar).unwrap();
+    writer.finish().unwrap();
+}
+
+// A tar archive holding one file under `name`, which the tar crate would refuse to write.
+fn tar_with_path(name: &[u8]) -> Vec<u8> {
+    let mut header = tar::Header::new_old();
+    header.as_old_mut().name[..name.len()].copy_from_slice(name);
+    header.set_size(4);
+    header.set_mode(0o644);
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_cksum();
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.append(&header, &b"evil"[..]).unwrap();
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn folders_round_trip() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let project = dir.join("project");
+    fs::create_dir_all(project.join("src/empty")).unwrap();
+    fs::write(project.join("README"), b"read me").unwrap();
+    fs::write(project.join("src/main.rs"), vec![7u8; 200_000]).unwrap();
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    File::options().write(true).open(project.join("README")).unwrap().set_modified(mtime).unwrap();
+
+    let encrypted = dir.join("project.aes");
+    let options = EncryptOptions { kdf: KDF, ..EncryptOptions::default() };
+    encrypt_folder(&project, &encrypted, PASSWORD, &options, &mut |_| true).unwrap();
+    let header = files::read_header(&encrypted).unwrap();
+    assert!(header.archive);
+
+    // Encrypted folders are not mistaken for files, or the other way round.
+    let result = decrypt_file(&encrypted, &dir.join("file"), PASSWORD, &DecryptOptions::default(), &mut |_| true);
+    assert!(matches!(result, Err(Error::InvalidOptions(_))));
+    // They can be verified like files, though.
+    assert!(verify_file(&encrypted, PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap().archive);
+
+    let restored = dir.join("restored");
+    decrypt(&encrypted, &restored).unwrap();
+    assert_eq!(fs::read(restored.join("README")).unwrap(), b"read me");
+    assert_eq!(fs::read(restored.join("src/main.rs")).unwrap(), vec![7u8; 200_000]);
+    assert!(restored.join("src/empty").is_dir());
+    assert_eq!(fs::metadata(restored.join("README")).unwrap().modified().unwrap(), mtime);
+
+    assert!(matches!(decrypt(&encrypted, &restored), Err(Error::AlreadyExists(_))));
+    // Without an output path the folder gets its original name, which is sealed.
+    let naming = OutputNaming { directory: Some(restored.clone()), ..OutputNaming::default() };
+    let mut output_for = |name: Option<&str>| Ok(naming.decrypted_path(&encrypted, name));
+    let named = decrypt_folder_named(&encrypted, PASSWORD, &DecryptOptions::default(), &mut output_for, &mut |_| true);
+    assert_eq!(named.unwrap(), restored.join("project"));
+    let wrong = decrypt_folder(&encrypted, &dir.join("wrong"), "wrong", &DecryptOptions::default(), &mut |_| true);
+    assert!(matches!(wrong, Err(Error::Authentication)));
+
+    // The output must not end up inside the archive it is written to.
+    let inside = encrypt_folder(&project, &project.join("self.aes"), PASSWORD, &options, &mut |_| true);
+    assert!(matches!(inside, Err(Error::InvalidOptions(_))));
+}
+
+#[test]
+fn modified_archives_leave_nothing_behind() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let project = dir.join("project");
+    fs::create_dir_all(&project).unwrap();
+    fs::write(project.join("data"), vec![1u8; 300_000]).unwrap();
+    let encrypted = dir.join("project.aes");
+    let options = EncryptOptions { kdf: KDF, chunk_size: 4096, ..EncryptOptions::default() };
+    encrypt_folder(&project, &encrypted, PASSWORD, &options, &mut |_| true).unwrap();
+
+    let mut bytes = fs::read(&encrypted).unwrap();
+    let len = bytes.len();
+    bytes[len - 100] ^= 1;
+    fs::write(&encrypted, &bytes).unwrap();
+    assert!(matches!(decrypt(&encrypted, &dir.join("restored")), Err(Error::Authentication)));
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 2);
+}
+
+#[test]
+fn paths_outside_the_output_are_refused() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    for name in [&b"../evil"[..], b"/tmp/evil", b"a/../../evil"] {
+        let encrypted = dir.join("evil.aes");
+        encrypt_tar(&encrypted, &tar_with_path(name));
+        let result = decrypt(&encrypted, &dir.join("restored"));
+        assert!(matches!(result, Err(Error::UnsafeArchivePath(_))), "{:?}", result);
+        assert!(!dir.join("evil").exists() && !dir.join("restored").exists());
+    }
+}
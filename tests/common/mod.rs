@@ -0,0 +1,21 @@
+/*
+Fixtures shared by the integration tests: a password, a KDF cheap enough to run many
+times, and temporary folders that are removed even when a test fails.
+ */
+#![allow(dead_code)]
+
+use aes256_encryption_gui_app::{EncryptOptions, Kdf};
+use tempfile::TempDir;
+
+pub const PASSWORD: &str = "password";
+pub const KDF: Kdf = Kdf::Pbkdf2Sha256 { iterations: 1000 };
+
+// Small chunks, so that short test data already spans several of them.
+pub fn fast_options() -> EncryptOptions {
+    EncryptOptions { kdf: KDF, chunk_size: 1024, ..EncryptOptions::default() }
+}
+
+// A fresh folder for one test, removed with everything in it when it is dropped.
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new().prefix("aes256-test-").tempdir().unwrap()
+}
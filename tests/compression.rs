@@ -0,0 +1,61 @@
+/*
+Compression: compressed files come back unchanged without being told how they were
+compressed, take less space when the data is repetitive, and are still authenticated.
+ */
+mod common;
+use common::{temp_dir, KDF, PASSWORD};
+
+use aes256_encryption_gui_app::archive::{decrypt_folder, encrypt_folder};
+use aes256_encryption_gui_app::{decrypt_stream, encrypt_stream, files, Compression, DecryptOptions, EncryptOptions, Error};
+use std::fs;
+
+fn options(compression: Compression) -> EncryptOptions {
+    EncryptOptions { kdf: KDF, chunk_size: 1024, compression, ..EncryptOptions::default() }
+}
+
+fn log_lines() -> Vec<u8> {
+    (0..2000).flat_map(|i| format!("2024-01-01 12:00:{:02} INFO request {} served\n", i % 60, i).into_bytes()).collect()
+}
+
+#[test]
+fn compressed_data_round_trips() {
+    let data = log_lines();
+    let plain = encrypt_stream(&data[..], Vec::new(), PASSWORD, &options(Compression::None)).unwrap();
+    for compression in [Compression::Deflate(6), Compression::Zstd(3), Compression::Zstd(19), Compression::Deflate(0)] {
+        let encrypted = encrypt_stream(&data[..], Vec::new(), PASSWORD, &options(compression)).unwrap();
+        if compression != Compression::Deflate(0) {
+            assert!(encrypted.len() < plain.len() / 4, "{} did not compress", compression);
+        }
+        let decrypted = decrypt_stream(&encrypted[..], Vec::new(), PASSWORD, &DecryptOptions::default()).unwrap();
+        assert_eq!(decrypted, data);
+
+        // Cutting off the end is still noticed, even where the compressed data before it
+        // is complete.
+        let truncated = &encrypted[..encrypted.len() - 20];
+        let result = decrypt_stream(truncated, Vec::new(), PASSWORD, &DecryptOptions::default());
+        assert!(matches!(result, Err(Error::Authentication)), "{:?}", result);
+    }
+
+    let invalid = encrypt_stream(&data[..], Vec::new(), PASSWORD, &options(Compression::Zstd(30)));
+    assert!(matches!(invalid, Err(Error::InvalidOptions(_))));
+}
+
+#[test]
+fn compression_is_recorded_and_parsed() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    fs::create_dir_all(dir.join("logs")).unwrap();
+    fs::write(dir.join("logs/server.log"), log_lines()).unwrap();
+
+    let encrypted = dir.join("logs.aes");
+    encrypt_folder(&dir.join("logs"), &encrypted, PASSWORD, &options(Compression::Zstd(9)), &mut |_| true).unwrap();
+    assert_eq!(files::read_header(&encrypted).unwrap().compression, Compression::Zstd(9));
+    decrypt_folder(&encrypted, &dir.join("restored"), PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap();
+    assert_eq!(fs::read(dir.join("restored/server.log")).unwrap(), log_lines());
+
+    assert_eq!("zstd".parse::<Compression>().unwrap(), Compression::Zstd(3));
+    assert_eq!("deflate:9".parse::<Compression>().unwrap(), Compression::Deflate(9));
+    assert_eq!("none".parse::<Compression>().unwrap(), Compression::None);
+    assert!("zstd:0".parse::<Compression>().is_err());
+    assert!("lzma".parse::<Compression>().is_err());
+}
@@ -0,0 +1,100 @@
+/*
+Key slots: passwords, keyfiles and public keys can be added to and removed from an
+encrypted file, any one of them opens it, and the payload is left as it was, as are the
+file's time and permissions.
+ */
+mod common;
+use common::{temp_dir, KDF};
+
+use aes256_encryption_gui_app::container::KeySlot;
+use aes256_encryption_gui_app::keyslot::{self, NewSlot};
+use aes256_encryption_gui_app::recipient::Identity;
+use aes256_encryption_gui_app::{decrypt_stream, encrypt_stream, files, keyfile, DecryptOptions, EncryptOptions, Error};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+// Writes "secret data" encrypted with `password` to a file in `dir`.
+fn encrypted_file(dir: &Path, password: &str) -> PathBuf {
+    let path = dir.join("data.aes");
+    let options = EncryptOptions { kdf: KDF, ..EncryptOptions::default() };
+    fs::write(&path, encrypt_stream(&b"secret data"[..], Vec::new(), password, &options).unwrap()).unwrap();
+    path
+}
+
+fn decrypt(path: &Path, password: &str, options: &DecryptOptions) -> Result<Vec<u8>, Error> {
+    decrypt_stream(&fs::read(path).unwrap()[..], Vec::new(), password, options)
+}
+
+fn add_password(path: &Path, unlock: &str, password: &str) -> Result<(), Error> {
+    let slot = NewSlot::Password { password, keyfiles: &[], kdf: KDF };
+    keyslot::add_slot(path, unlock, &DecryptOptions::default(), &slot).map(|_| ())
+}
+
+#[test]
+fn passwords_can_be_added_and_removed() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let path = encrypted_file(dir, "first");
+    let before = fs::read(&path).unwrap();
+    let options = DecryptOptions::default();
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+
+    assert!(matches!(add_password(&path, "wrong", "second"), Err(Error::Authentication)));
+    assert_eq!(fs::read(&path).unwrap(), before);
+    add_password(&path, "first", "second").unwrap();
+    assert_eq!(decrypt(&path, "first", &options).unwrap(), b"secret data");
+    assert_eq!(decrypt(&path, "second", &options).unwrap(), b"secret data");
+    // The file keeps its time and permissions.
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    // The payload is not re-encrypted: the file only grew by the new slot.
+    let after = fs::read(&path).unwrap();
+    let payload = b"secret data".len() + 16; // a single chunk and its tag
+    assert_eq!(after[after.len() - payload..], before[before.len() - payload..]);
+
+    let header = keyslot::remove_slot(&path, "second", &options, 0).unwrap();
+    assert_eq!(header.slots.len(), 1);
+    assert!(matches!(decrypt(&path, "first", &options), Err(Error::Authentication)));
+    assert_eq!(decrypt(&path, "second", &options).unwrap(), b"secret data");
+    assert!(matches!(keyslot::remove_slot(&path, "second", &options, 0), Err(Error::InvalidOptions(_))));
+    assert!(matches!(keyslot::remove_slot(&path, "second", &options, 1), Err(Error::InvalidOptions(_))));
+}
+
+#[test]
+fn keyfiles_and_public_keys_can_be_added() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let path = encrypted_file(dir, "password");
+    let keyfiles = [dir.join("new.key")];
+    keyfile::generate(&keyfiles[0]).unwrap();
+    let identity = Identity::generate();
+
+    let slot = NewSlot::Password { password: "", keyfiles: &keyfiles, kdf: KDF };
+    keyslot::add_slot(&path, "password", &DecryptOptions::default(), &slot).unwrap();
+    keyslot::add_slot(&path, "password", &DecryptOptions::default(), &NewSlot::Recipient(identity.recipient())).unwrap();
+
+    let header = files::read_header(&path).unwrap();
+    assert!(matches!(header.slots[..], [KeySlot::Password { keyfiles: 0, .. }, KeySlot::Password { keyfiles: 1, .. }, KeySlot::Recipient(_)]));
+
+    let with_keyfile = DecryptOptions { keyfiles: keyfiles.to_vec(), ..DecryptOptions::default() };
+    assert_eq!(decrypt(&path, "", &with_keyfile).unwrap(), b"secret data");
+    let with_identity = DecryptOptions { identities: vec![identity], ..DecryptOptions::default() };
+    assert_eq!(decrypt(&path, "", &with_identity).unwrap(), b"secret data");
+
+    // A slot can be opened with the new keyfile to add another.
+    let slot = NewSlot::Password { password: "third", keyfiles: &[], kdf: KDF };
+    keyslot::add_slot(&path, "", &with_keyfile, &slot).unwrap();
+    assert_eq!(decrypt(&path, "third", &DecryptOptions::default()).unwrap(), b"secret data");
+}
@@ -0,0 +1,65 @@
+/*
+Keyfiles: files encrypted with keyfiles only decrypt with the same keyfiles (in any order)
+and the password, and the header says how many are needed.
+ */
+mod common;
+use common::{temp_dir, KDF, PASSWORD};
+
+use aes256_encryption_gui_app::{decrypt_stream, encrypt_stream, keyfile, DecryptOptions, EncryptOptions, Error};
+use std::fs;
+use std::path::PathBuf;
+
+fn encrypt(password: &str, keyfiles: &[PathBuf]) -> Result<Vec<u8>, Error> {
+    let options = EncryptOptions {
+        kdf: KDF,
+        keyfiles: keyfiles.to_vec(),
+        ..EncryptOptions::default()
+    };
+    encrypt_stream(&b"secret data"[..], Vec::new(), password, &options)
+}
+
+fn decrypt(encrypted: &[u8], password: &str, keyfiles: &[PathBuf]) -> Result<Vec<u8>, Error> {
+    let options = DecryptOptions { keyfiles: keyfiles.to_vec(), ..DecryptOptions::default() };
+    decrypt_stream(encrypted, Vec::new(), password, &options)
+}
+
+#[test]
+fn keyfiles_are_needed_together_with_the_password() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (first, second, other) = (dir.join("first.key"), dir.join("second.key"), dir.join("other.key"));
+    for path in [&first, &second, &other] {
+        keyfile::generate(path).unwrap();
+    }
+    assert_eq!(fs::read(&first).unwrap().len(), keyfile::KEYFILE_LEN);
+
+    let encrypted = encrypt(PASSWORD, &[first.clone(), second.clone()]).unwrap();
+    assert_eq!(decrypt(&encrypted, PASSWORD, &[second.clone(), first.clone()]).unwrap(), b"secret data");
+
+    let result = decrypt(&encrypted, "wrong", &[first.clone(), second.clone()]);
+    assert!(matches!(result, Err(Error::Authentication)));
+    let result = decrypt(&encrypted, PASSWORD, &[first.clone(), other]);
+    assert!(matches!(result, Err(Error::Authentication)));
+    let result = decrypt(&encrypted, PASSWORD, &[first]);
+    assert!(matches!(result, Err(Error::KeyfileCount { needed: 2, given: 1 })));
+    let result = decrypt(&encrypted, PASSWORD, &[]);
+    assert!(matches!(result, Err(Error::KeyfileCount { needed: 2, given: 0 })));
+}
+
+#[test]
+fn a_keyfile_can_replace_the_password() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let keys = [dir.join("alone.key")];
+    keyfile::generate(&keys[0]).unwrap();
+
+    let encrypted = encrypt("", &keys).unwrap();
+    assert_eq!(decrypt(&encrypted, "", &keys).unwrap(), b"secret data");
+    assert!(matches!(encrypt("", &[]), Err(Error::EmptyPassword)));
+
+    // A keyfile is never replaced, and an empty one is refused.
+    assert!(matches!(keyfile::generate(&keys[0]), Err(Error::AlreadyExists(_))));
+    let empty = dir.join("empty.key");
+    fs::write(&empty, b"").unwrap();
+    assert!(matches!(encrypt(PASSWORD, &[empty]), Err(Error::InvalidOptions(_))));
+}
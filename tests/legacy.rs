@@ -0,0 +1,45 @@
+/*
+Files from the first release of the app, which have no header: the whole file is
+AES-256-CBC under a key made from the password bytes. They are still decrypted, unless
+the options turn that off. tests/fixtures/notes.txt_Encrypt was written by that release.
+ */
+mod common;
+use common::{temp_dir, PASSWORD};
+
+use aes256_encryption_gui_app::{decrypt_file, decrypt_file_named, files, DecryptOptions, Error, OutputNaming};
+use std::fs;
+use std::path::Path;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/notes.txt_Encrypt");
+const CONTENTS: &[u8] = b"Written by the first release of the app, before files had a header.\n";
+
+#[test]
+fn first_release_files_are_decrypted() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let fixture = Path::new(FIXTURE);
+    assert!(files::is_legacy(fixture) && !files::is_encrypted(fixture));
+
+    // The output gets the name the first release added "_Encrypt" to.
+    let naming = OutputNaming { directory: Some(dir.to_path_buf()), ..OutputNaming::default() };
+    let mut output_for = |name: Option<&str>| Ok(naming.decrypted_path(fixture, name));
+    let output = decrypt_file_named(fixture, PASSWORD, &DecryptOptions::default(), &mut output_for, &mut |_| true).unwrap();
+    assert_eq!(output, dir.join("notes.txt"));
+    assert_eq!(fs::read(&output).unwrap(), CONTENTS);
+}
+
+#[test]
+fn first_release_files_can_be_refused() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let fixture = Path::new(FIXTURE);
+    let output = dir.join("notes.txt");
+
+    // Only the padding shows the password is wrong, which it does not always do.
+    let wrong = decrypt_file(fixture, &output, "wrong", &DecryptOptions::default(), &mut |_| true);
+    assert!(matches!(wrong, Err(Error::Authentication)), "{:?}", wrong);
+    let options = DecryptOptions { allow_legacy_cbc: false, ..DecryptOptions::default() };
+    let refused = decrypt_file(fixture, &output, PASSWORD, &options, &mut |_| true);
+    assert!(matches!(refused, Err(Error::UnsupportedFormat(_))), "{:?}", refused);
+    assert!(!output.exists());
+}
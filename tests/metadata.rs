@@ -0,0 +1,118 @@
+/*
+Metadata of the original file: its name, time and permissions come back with it, only
+those who can decrypt the file can read them, the name can be left out, and a plaintext
+of the wrong size is refused. The time a file was encrypted is recorded for everyone to see.
+ */
+mod common;
+use common::{temp_dir, KDF, PASSWORD};
+
+use aes256_encryption_gui_app::container::Header;
+use aes256_encryption_gui_app::metadata::format_time;
+use aes256_encryption_gui_app::stream::{self, StreamWriter};
+use aes256_encryption_gui_app::{decrypt_file, decrypt_file_named, decrypt_stream, encrypt_file, encrypt_stream, files, kdf, keyslot, Cipher, Compression, DecryptOptions, EncryptOptions, Error, OutputNaming};
+use std::fs::{self, File};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[test]
+fn time_and_permissions_are_restored() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("notes.txt");
+    fs::write(&plain, b"meeting at noon").unwrap();
+    let mtime = UNIX_EPOCH + Duration::new(1_200_000_000, 123_456_789);
+    File::options().write(true).open(&plain).unwrap().set_modified(mtime).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&plain, fs::Permissions::from_mode(0o640)).unwrap();
+    }
+
+    let encrypted = dir.join("notes.txt.aes");
+    let options = EncryptOptions { kdf: KDF, compression: Compression::Zstd(3), ..EncryptOptions::default() };
+    encrypt_file(&plain, &encrypted, PASSWORD, &options, &mut |_| true).unwrap();
+    let header = files::read_header(&encrypted).unwrap();
+    assert!(header.metadata.is_some());
+    assert_eq!(header.file_size, Some(15));
+
+    let metadata = files::read_metadata(&encrypted, PASSWORD, &DecryptOptions::default()).unwrap().unwrap();
+    assert_eq!(metadata.name.as_deref(), Some("notes.txt"));
+    assert_eq!(metadata.modified, Some(mtime));
+    let wrong = files::read_metadata(&encrypted, "wrong", &DecryptOptions::default());
+    assert!(matches!(wrong, Err(Error::Authentication)));
+
+    let restored = dir.join("restored.txt");
+    decrypt_file(&encrypted, &restored, PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap();
+    assert_eq!(fs::read(&restored).unwrap(), b"meeting at noon");
+    assert_eq!(fs::metadata(&restored).unwrap().modified().unwrap(), mtime);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(metadata.permissions, Some(0o640));
+        assert_eq!(fs::metadata(&restored).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+}
+
+#[test]
+fn names_can_be_left_out() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("layoffs-2026.xlsx");
+    fs::write(&plain, b"names").unwrap();
+    let encrypted = dir.join("a.aes");
+    let options = EncryptOptions { kdf: KDF, store_name: false, ..EncryptOptions::default() };
+    encrypt_file(&plain, &encrypted, PASSWORD, &options, &mut |_| true).unwrap();
+
+    let metadata = files::read_metadata(&encrypted, PASSWORD, &DecryptOptions::default()).unwrap().unwrap();
+    assert_eq!(metadata.name, None);
+    let mut output_for = |name: Option<&str>| Ok(OutputNaming::default().decrypted_path(&encrypted, name));
+    let output = decrypt_file_named(&encrypted, PASSWORD, &DecryptOptions::default(), &mut output_for, &mut |_| true);
+    assert_eq!(output.unwrap(), dir.join("a"));
+}
+
+#[test]
+fn the_recorded_size_is_checked() {
+    // A header that records a size the payload does not have, as a faulty writer could.
+    let key = KDF.derive_key(PASSWORD.as_bytes(), &kdf::generate_salt());
+    let header = Header {
+        cipher: Cipher::Aes256Gcm,
+        slots: vec![keyslot::password_slot(&key, PASSWORD, &[], KDF).unwrap()],
+        nonce: stream::generate_nonce_prefix().to_vec(),
+        chunk_size: stream::DEFAULT_CHUNK_SIZE,
+        file_size: Some(10),
+        archive: false,
+        compression: Compression::None,
+        metadata: None,
+        created: None,
+    };
+    let mut encrypted = Vec::new();
+    header.write_to(&mut encrypted).unwrap();
+    let aad = header.authenticated_bytes().unwrap();
+    let mut writer = StreamWriter::new(&mut encrypted, header.cipher, key.as_bytes(), &header.nonce, stream::DEFAULT_CHUNK_SIZE, aad);
+    writer.write_all(b"short").unwrap();
+    writer.finish().unwrap();
+
+    let result = decrypt_stream(&encrypted[..], Vec::new(), PASSWORD, &DecryptOptions::default());
+    assert!(matches!(result, Err(Error::SizeMismatch { expected: 10, found: 5 })), "{:?}", result);
+
+    let temp = temp_dir();
+    let dir = temp.path();
+    fs::write(dir.join("short.aes"), &encrypted).unwrap();
+    let result = decrypt_file(&dir.join("short.aes"), &dir.join("short"), PASSWORD, &DecryptOptions::default(), &mut |_| true);
+    assert!(matches!(result, Err(Error::SizeMismatch { .. })));
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+}
+
+#[test]
+fn the_creation_time_is_recorded() {
+    let before = SystemTime::now() - Duration::from_secs(1);
+    let options = EncryptOptions { kdf: KDF, ..EncryptOptions::default() };
+    let encrypted = encrypt_stream(&b"data"[..], Vec::new(), PASSWORD, &options).unwrap();
+    let (header, _) = Header::read_from(&mut &encrypted[..]).unwrap();
+    let created = header.created.unwrap();
+    assert!(before <= created && created <= SystemTime::now());
+
+    assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(1_200_000_000)), "2008-01-10 21:20:00 UTC");
+    assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(951_825_599)), "2000-02-29 11:59:59 UTC");
+}
@@ -0,0 +1,94 @@
+/*
+Output naming: encrypted files get an extension, decrypted files get their original
+name back once the file has been opened, and both can be sent to another folder.
+ */
+mod common;
+use common::{fast_options, temp_dir, PASSWORD};
+
+use aes256_encryption_gui_app::naming::unused_path;
+use aes256_encryption_gui_app::{decrypt_file, decrypt_file_named, encrypt_file, DecryptOptions, Error, OutputNaming};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn encrypted_files_get_the_extension() {
+    let naming = OutputNaming::default();
+    assert_eq!(naming.encrypted_path(Path::new("dir/report.pdf")), Path::new("dir/report.pdf.aes"));
+
+    let naming = OutputNaming { extension: String::from(".enc"), directory: Some(PathBuf::from("out")) };
+    assert_eq!(naming.encrypted_path(Path::new("dir/report.pdf")), Path::new("out/report.pdf.enc"));
+}
+
+#[test]
+fn decrypted_files_get_their_original_name() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("report.pdf");
+    let encrypted = dir.join("renamed by someone.bin");
+    fs::write(&plain, b"contents").unwrap();
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+    // The name is sealed with the key, so it is not in the file for anyone to read.
+    assert!(!fs::read(&encrypted).unwrap().windows(6).any(|w| w == b"report"));
+
+    let naming = OutputNaming { directory: Some(dir.join("restored")), ..OutputNaming::default() };
+    fs::create_dir(dir.join("restored")).unwrap();
+    let mut output_for = |name: Option<&str>| Ok(naming.decrypted_path(&encrypted, name));
+    let output = decrypt_file_named(&encrypted, PASSWORD, &DecryptOptions::default(), &mut output_for, &mut |_| true).unwrap();
+    assert_eq!(output, dir.join("restored").join("report.pdf"));
+    assert_eq!(fs::read(&output).unwrap(), b"contents");
+
+    let mut never = |_: Option<&str>| -> Result<PathBuf, Error> { panic!("named before the key was found") };
+    let wrong = decrypt_file_named(&encrypted, "wrong", &DecryptOptions::default(), &mut never, &mut |_| true);
+    assert!(matches!(wrong, Err(Error::Authentication)));
+}
+
+#[test]
+fn without_an_original_name_the_extension_is_removed() {
+    let naming = OutputNaming::default();
+    assert_eq!(naming.decrypted_path(Path::new("dir/notes.txt.aes"), None), Path::new("dir/notes.txt"));
+    assert_eq!(naming.decrypted_path(Path::new("dir/notes.txt.bin"), None), Path::new("dir/notes.txt.bin.decrypted"));
+    // Names that are not a plain file name are not used.
+    for name in ["../notes.txt", "..", ".", "", "a/b", "a\\b", "C:evil", "C:..\\x", "/etc/passwd"] {
+        assert_eq!(naming.decrypted_path(Path::new("dir/notes.txt.aes"), Some(name)), Path::new("dir/notes.txt"), "{:?}", name);
+    }
+    assert_eq!(naming.decrypted_path(Path::new("dir/notes.txt.aes"), Some("..notes")), Path::new("dir/..notes"));
+}
+
+#[test]
+fn naming_a_file_that_is_not_encrypted_fails() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("plain.txt.aes");
+    fs::write(&plain, b"not encrypted at all").unwrap();
+    let mut never = |_: Option<&str>| -> Result<PathBuf, Error> { panic!("a file that is not encrypted was named") };
+    let result = decrypt_file_named(&plain, PASSWORD, &DecryptOptions::default(), &mut never, &mut |_| true);
+    assert!(matches!(result, Err(Error::UnsupportedFormat(_))));
+}
+
+#[test]
+fn the_input_is_never_overwritten_by_its_own_output() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("report.pdf");
+    let encrypted = dir.join("report.pdf.aes");
+    fs::write(&plain, b"contents").unwrap();
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+
+    let result = decrypt_file(&encrypted, &encrypted, PASSWORD, &DecryptOptions::default(), &mut |_| true);
+    assert!(matches!(result, Err(Error::OutputIsInput(_))));
+    let options = DecryptOptions { overwrite: true, ..DecryptOptions::default() };
+    assert!(matches!(decrypt_file(&encrypted, &encrypted, PASSWORD, &options, &mut |_| true), Err(Error::OutputIsInput(_))));
+    assert!(decrypt_file(&encrypted, &plain, PASSWORD, &options, &mut |_| true).is_ok());
+}
+
+#[test]
+fn unused_paths_number_the_name() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let path = dir.join("report.pdf");
+    assert_eq!(unused_path(&path), path);
+    fs::write(&path, b"1").unwrap();
+    assert_eq!(unused_path(&path), dir.join("report (1).pdf"));
+    fs::write(dir.join("report (1).pdf"), b"2").unwrap();
+    assert_eq!(unused_path(&path), dir.join("report (2).pdf"));
+}
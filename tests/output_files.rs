@@ -0,0 +1,121 @@
+/*
+How output files are written: never over an existing file unless asked to, and never
+left half-written under their final name.
+ */
+mod common;
+use common::{fast_options, temp_dir, PASSWORD};
+
+use aes256_encryption_gui_app::{decrypt_file, encrypt_file, DecryptOptions, EncryptOptions, Error};
+use std::fs;
+use std::path::Path;
+
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn existing_outputs_are_kept_unless_overwriting() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted) = (dir.join("plain.txt"), dir.join("plain.txt.aes"));
+    fs::write(&plain, b"new contents").unwrap();
+    fs::write(&encrypted, b"precious").unwrap();
+
+    let result = encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true);
+    assert!(matches!(result, Err(Error::AlreadyExists(path)) if path == encrypted));
+    assert_eq!(fs::read(&encrypted).unwrap(), b"precious");
+
+    let options = EncryptOptions { overwrite: true, ..fast_options() };
+    encrypt_file(&plain, &encrypted, PASSWORD, &options, &mut |_| true).unwrap();
+    assert_ne!(fs::read(&encrypted).unwrap(), b"precious");
+    assert_eq!(file_names(dir), ["plain.txt", "plain.txt.aes"]);
+}
+
+#[test]
+fn a_failed_overwrite_keeps_the_old_file() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted, decrypted) = (dir.join("plain.txt"), dir.join("plain.txt.aes"), dir.join("out.txt"));
+    fs::write(&plain, vec![7u8; 300_000]).unwrap();
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+    fs::write(&decrypted, b"previous good decryption").unwrap();
+
+    // Cut the file short, so decryption fails after some plaintext was already written.
+    let contents = fs::read(&encrypted).unwrap();
+    fs::write(&encrypted, &contents[..contents.len() - 100]).unwrap();
+    let options = DecryptOptions { overwrite: true, ..DecryptOptions::default() };
+    let result = decrypt_file(&encrypted, &decrypted, PASSWORD, &options, &mut |_| true);
+
+    assert!(matches!(result, Err(Error::Authentication)));
+    assert_eq!(fs::read(&decrypted).unwrap(), b"previous good decryption");
+    assert_eq!(file_names(dir), ["out.txt", "plain.txt", "plain.txt.aes"]);
+}
+
+#[test]
+fn a_missing_output_folder_is_reported() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("plain.txt");
+    fs::write(&plain, b"contents").unwrap();
+    let missing = dir.join("missing");
+
+    let result = encrypt_file(&plain, &missing.join("plain.txt.aes"), PASSWORD, &fast_options(), &mut |_| true);
+    assert!(matches!(result, Err(Error::NotFound(path)) if path == missing));
+}
+
+#[test]
+fn originals_are_removed_once_the_output_decrypts_to_them() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted, decrypted) = (dir.join("plain.txt"), dir.join("plain.txt.aes"), dir.join("out.txt"));
+    fs::write(&plain, b"sensitive contents").unwrap();
+    let options = EncryptOptions { remove_original: true, overwrite: true, ..fast_options() };
+
+    encrypt_file(&plain, &encrypted, PASSWORD, &options, &mut |_| true).unwrap();
+    assert_eq!(file_names(dir), ["plain.txt.aes"]);
+    decrypt_file(&encrypted, &decrypted, PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap();
+    assert_eq!(fs::read(&decrypted).unwrap(), b"sensitive contents");
+
+    // An original with another hard link is kept, since overwriting it would destroy
+    // the other link's contents too.
+    if cfg!(unix) {
+        fs::rename(&decrypted, &plain).unwrap();
+        fs::hard_link(&plain, dir.join("link.txt")).unwrap();
+        let result = encrypt_file(&plain, &encrypted, PASSWORD, &options, &mut |_| true);
+        assert!(matches!(result, Err(Error::OriginalKept(_))));
+        assert_eq!(fs::read(&plain).unwrap(), b"sensitive contents");
+    }
+    // So is an original given as a symbolic link, which the link's target would pay for.
+    #[cfg(unix)]
+    {
+        fs::remove_file(dir.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(&plain, dir.join("link.txt")).unwrap();
+        let result = encrypt_file(&dir.join("link.txt"), &encrypted, PASSWORD, &options, &mut |_| true);
+        assert!(matches!(result, Err(Error::OriginalKept(_))), "{:?}", result);
+        assert_eq!(fs::read(&plain).unwrap(), b"sensitive contents");
+    }
+}
+
+#[test]
+fn checking_the_output_continues_the_progress() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let plain = dir.join("plain.txt");
+    fs::write(&plain, vec![7u8; 100_000]).unwrap();
+    let options = EncryptOptions { remove_original: true, ..fast_options() };
+
+    let mut reported = Vec::new();
+    encrypt_file(&plain, &dir.join("plain.txt.aes"), PASSWORD, &options, &mut |n| {
+        reported.push(n);
+        true
+    })
+    .unwrap();
+    assert!(reported.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", reported);
+    let last = *reported.last().unwrap();
+    assert!(50_000 < last && last <= 100_000, "{}", last);
+}
@@ -0,0 +1,34 @@
+/*
+Password checks: new files need a password, and the strength estimate ranks passwords
+the way a guessing attack would.
+ */
+mod common;
+use common::KDF;
+
+use aes256_encryption_gui_app::strength::{estimate, Strength};
+use aes256_encryption_gui_app::{decrypt_stream, encrypt_stream, DecryptOptions, EncryptOptions, Error};
+
+#[test]
+fn empty_passwords_are_refused_for_new_files() {
+    let options = EncryptOptions { kdf: KDF, ..EncryptOptions::default() };
+    let result = encrypt_stream(&b"data"[..], Vec::new(), "", &options);
+    assert!(matches!(result, Err(Error::EmptyPassword)));
+
+    let encrypted = encrypt_stream(&b"data"[..], Vec::new(), "x", &options).unwrap();
+    let result = decrypt_stream(&encrypted[..], Vec::new(), "", &DecryptOptions::default());
+    assert!(matches!(result, Err(Error::Authentication)));
+}
+
+#[test]
+fn common_and_patterned_passwords_are_weak() {
+    for password in ["", "password", "Password", "123456789", "aaaaaaaaaaaa", "abcdefghijkl", "qwerty"] {
+        assert_eq!(estimate(password).strength, Strength::VeryWeak, "{:?}", password);
+    }
+}
+
+#[test]
+fn longer_and_more_varied_passwords_are_stronger() {
+    assert!(estimate("tr0ub4dor").bits > estimate("troubador").bits);
+    assert!(estimate("correct horse battery staple").strength >= Strength::Strong);
+    assert_eq!(estimate("x7#Qm!2vR9$kLp@4Wz&8").strength, Strength::VeryStrong);
+}
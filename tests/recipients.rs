@@ -0,0 +1,70 @@
+/*
+Recipients: files encrypted to public keys decrypt with any one of the matching secret
+keys (or the password, if one was given too) and nothing else, and keys are read and
+written the way age writes them.
+ */
+mod common;
+use common::{temp_dir, KDF};
+
+use aes256_encryption_gui_app::recipient::{self, Identity, Recipient};
+use aes256_encryption_gui_app::{decrypt_stream, encrypt_stream, DecryptOptions, EncryptOptions, Error};
+use std::fs;
+
+fn encrypt(password: &str, recipients: &[Recipient]) -> Result<Vec<u8>, Error> {
+    let options = EncryptOptions { kdf: KDF, recipients: recipients.to_vec(), ..EncryptOptions::default() };
+    encrypt_stream(&b"secret data"[..], Vec::new(), password, &options)
+}
+
+fn decrypt(encrypted: &[u8], identities: &[Identity]) -> Result<Vec<u8>, Error> {
+    let options = DecryptOptions { identities: identities.to_vec(), ..DecryptOptions::default() };
+    decrypt_stream(encrypted, Vec::new(), "", &options)
+}
+
+#[test]
+fn any_recipient_can_decrypt() {
+    // Alice and Bob are recipients, Eve is not.
+    let keys = [Identity::generate(), Identity::generate(), Identity::generate()];
+    let encrypted = encrypt("", &[keys[0].recipient(), keys[1].recipient()]).unwrap();
+
+    assert_eq!(decrypt(&encrypted, &keys[..1]).unwrap(), b"secret data");
+    assert_eq!(decrypt(&encrypted, &keys[1..]).unwrap(), b"secret data");
+    assert!(matches!(decrypt(&encrypted, &keys[2..]), Err(Error::NoMatchingIdentity)));
+    assert!(matches!(decrypt(&encrypted, &[]), Err(Error::NoMatchingIdentity)));
+
+    // A password given as well opens the file too.
+    let encrypted = encrypt("password", &[keys[0].recipient()]).unwrap();
+    assert_eq!(decrypt(&encrypted, &keys[..1]).unwrap(), b"secret data");
+    let options = DecryptOptions::default();
+    assert_eq!(decrypt_stream(&encrypted[..], Vec::new(), "password", &options).unwrap(), b"secret data");
+}
+
+#[test]
+fn keys_use_the_age_encoding() {
+    // The X25519 test vector from the age specification.
+    let identity: Identity = "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX".parse().unwrap();
+    let expected = "age1zvkyg2lqzraa2lnjvqej32nkuu0ues2s82hzrye869xeexvn73equnujwj";
+    assert_eq!(identity.recipient().to_string(), expected);
+    assert_eq!(expected.parse::<Recipient>().unwrap(), identity.recipient());
+    assert_eq!(identity.to_secret_string().parse::<Identity>().unwrap(), identity);
+
+    assert!(matches!("age1notakey".parse::<Recipient>(), Err(Error::InvalidKey(_))));
+    assert!(matches!(expected.parse::<Identity>(), Err(Error::InvalidKey(_))));
+}
+
+#[test]
+fn identity_files_round_trip() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let path = dir.join("identity.key");
+    let identity = Identity::generate();
+    recipient::write_identity(&path, &identity).unwrap();
+    assert!(matches!(recipient::write_identity(&path, &identity), Err(Error::AlreadyExists(_))));
+
+    assert_eq!(recipient::read_identities(&path).unwrap(), vec![identity.clone()]);
+    let recipients = dir.join("recipients.txt");
+    fs::write(&recipients, format!("# a colleague\n\n{}\n", identity.recipient())).unwrap();
+    assert_eq!(recipient::read_recipients(&recipients).unwrap(), vec![identity.recipient()]);
+
+    fs::write(&recipients, "# nobody\n").unwrap();
+    assert!(matches!(recipient::read_recipients(&recipients), Err(Error::InvalidKey(_))));
+}
@@ -0,0 +1,180 @@
+/*
+Round trips through the public library API: whatever encrypt_file and encrypt_stream
+write must come back unchanged from decrypt_file and decrypt_stream, and anything that
+was tampered with or is opened with the wrong password must be refused. Most tests use
+a cheap KDF so they run quickly; the defaults are covered once.
+ */
+mod common;
+use common::{fast_options, temp_dir, PASSWORD};
+
+use aes256_encryption_gui_app::{
+    decrypt_file, decrypt_stream, encrypt_file, encrypt_stream, files, verify_file, Cipher, DecryptOptions, EncryptOptions,
+    Error, Kdf,
+};
+use std::fs;
+
+// Deterministic, non-repeating test data.
+fn sample(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + i / 251) as u8).collect()
+}
+
+fn encrypt(data: &[u8], options: &EncryptOptions) -> Vec<u8> {
+    encrypt_stream(data, Vec::new(), PASSWORD, options).unwrap()
+}
+
+fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>, Error> {
+    decrypt_stream(data, Vec::new(), password, &DecryptOptions::default())
+}
+
+#[test]
+fn stream_round_trip_at_chunk_boundaries() {
+    for len in [0, 1, 1023, 1024, 1025, 4096, 10_000] {
+        let data = sample(len);
+        let encrypted = encrypt(&data, &fast_options());
+        assert_eq!(decrypt(&encrypted, PASSWORD).unwrap(), data, "length {}", len);
+    }
+}
+
+#[test]
+fn stream_round_trip_with_each_cipher() {
+    for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+        let data = sample(5000);
+        let encrypted = encrypt(&data, &EncryptOptions { cipher, ..fast_options() });
+        assert_eq!(decrypt(&encrypted, PASSWORD).unwrap(), data, "{}", cipher);
+    }
+}
+
+#[test]
+fn default_options_round_trip() {
+    let data = sample(100_000);
+    let encrypted = encrypt(&data, &EncryptOptions::default());
+    assert_eq!(decrypt(&encrypted, PASSWORD).unwrap(), data);
+}
+
+#[test]
+fn encrypting_twice_gives_different_output() {
+    let data = sample(100);
+    assert_ne!(encrypt(&data, &fast_options()), encrypt(&data, &fast_options()));
+}
+
+#[test]
+fn wrong_password_is_rejected() {
+    let encrypted = encrypt(&sample(3000), &fast_options());
+    assert!(matches!(decrypt(&encrypted, "wrong"), Err(Error::Authentication)));
+}
+
+#[test]
+fn modified_bytes_are_detected() {
+    let encrypted = encrypt(&sample(3000), &fast_options());
+    // Flip a bit in the header, in the first chunk and in the last chunk.
+    for position in [10, encrypted.len() / 2, encrypted.len() - 1] {
+        let mut modified = encrypted.clone();
+        modified[position] ^= 1;
+        assert!(decrypt(&modified, PASSWORD).is_err(), "bit flipped at {}", position);
+    }
+}
+
+#[test]
+fn truncation_is_detected() {
+    let encrypted = encrypt(&sample(3000), &fast_options());
+    for len in [encrypted.len() - 1, encrypted.len() - 500] {
+        assert!(matches!(decrypt(&encrypted[..len], PASSWORD), Err(Error::Authentication)), "cut to {}", len);
+    }
+}
+
+#[test]
+fn plain_data_is_not_decrypted() {
+    let result = decrypt(b"just some text, not an encrypted file", PASSWORD);
+    assert!(matches!(result, Err(Error::UnsupportedFormat(_))));
+}
+
+#[test]
+fn invalid_options_are_rejected() {
+    let no_chunks = EncryptOptions { chunk_size: 0, ..fast_options() };
+    let no_iterations = EncryptOptions { kdf: Kdf::Pbkdf2Sha256 { iterations: 0 }, ..fast_options() };
+    for options in [no_chunks, no_iterations] {
+        let result = encrypt_stream(&b"data"[..], Vec::new(), PASSWORD, &options);
+        assert!(matches!(result, Err(Error::InvalidOptions(_))), "{:?}", options);
+    }
+}
+
+#[test]
+fn file_round_trip() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted, decrypted) = (dir.join("plain.txt"), dir.join("encrypted"), dir.join("decrypted.txt"));
+    let data = sample(50_000);
+    fs::write(&plain, &data).unwrap();
+
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+    let header = verify_file(&encrypted, PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap();
+    assert!(header.metadata.is_some());
+    decrypt_file(&encrypted, &decrypted, PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap();
+    assert_eq!(fs::read(&decrypted).unwrap(), data);
+}
+
+#[test]
+fn files_and_streams_are_interchangeable() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted) = (dir.join("plain.txt"), dir.join("encrypted"));
+    let data = sample(7000);
+    fs::write(&plain, &data).unwrap();
+
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+    assert_eq!(decrypt(&fs::read(&encrypted).unwrap(), PASSWORD).unwrap(), data);
+
+    fs::write(&encrypted, encrypt(&data, &fast_options())).unwrap();
+    let decrypted = dir.join("decrypted.txt");
+    decrypt_file(&encrypted, &decrypted, PASSWORD, &DecryptOptions::default(), &mut |_| true).unwrap();
+    assert_eq!(fs::read(&decrypted).unwrap(), data);
+}
+
+#[test]
+fn failed_decryption_leaves_no_output() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted, decrypted) = (dir.join("plain.txt"), dir.join("encrypted"), dir.join("decrypted.txt"));
+    fs::write(&plain, sample(5000)).unwrap();
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+
+    let result = decrypt_file(&encrypted, &decrypted, "wrong", &DecryptOptions::default(), &mut |_| true);
+    assert!(matches!(result, Err(Error::Authentication)));
+    assert!(!decrypted.exists());
+}
+
+#[test]
+fn cancelling_removes_the_partial_output() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted) = (dir.join("plain.txt"), dir.join("encrypted"));
+    fs::write(&plain, sample(200_000)).unwrap();
+
+    let result = encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |processed| processed < 50_000);
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(!encrypted.exists());
+}
+
+#[test]
+fn missing_input_is_reported() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let missing = dir.join("missing.txt");
+    let result = encrypt_file(&missing, &dir.join("out"), PASSWORD, &fast_options(), &mut |_| true);
+    assert!(matches!(result, Err(Error::NotFound(path)) if path == missing));
+}
+
+#[test]
+fn encrypted_files_are_recognised() {
+    let temp = temp_dir();
+    let dir = temp.path();
+    let (plain, encrypted, empty) = (dir.join("plain.txt"), dir.join("encrypted"), dir.join("empty"));
+    fs::write(&plain, sample(100)).unwrap();
+    fs::write(&empty, b"").unwrap();
+    encrypt_file(&plain, &encrypted, PASSWORD, &fast_options(), &mut |_| true).unwrap();
+
+    assert!(files::is_encrypted(&encrypted));
+    assert!(!files::is_encrypted(&plain));
+    assert!(!files::is_encrypted(&empty));
+    assert!(!files::is_encrypted(&dir.join("missing")));
+}